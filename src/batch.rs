@@ -0,0 +1,290 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use librarian::{Field, Index, Library, Page, Query, SearchResults, SnippetOptions};
+
+use crate::cli::Options;
+
+/// One query of a batch, given as a JSON line, or of a request to the
+/// server. Options left out take their value from the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchQuery {
+    /// Copied to the output, to match results to queries.
+    pub id: Option<Value>,
+    pub query: String,
+    pub limit: Option<usize>,
+    pub page: Option<usize>,
+    pub offset: Option<usize>,
+    /// Facet values to narrow the results to, e.g. `{"subject": "Bible"}`.
+    #[serde(default)]
+    pub filters: BTreeMap<String, String>,
+    pub max_snippets: Option<usize>,
+    pub sentences: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct BatchResult<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a Value>,
+    #[serde(flatten)]
+    results: &'a SearchResults,
+}
+
+#[derive(Serialize)]
+struct BatchError<'a> {
+    line: usize,
+    error: &'a str,
+}
+
+/// Runs every query read from `input` against one loaded index and writes
+/// the results of each as one JSON line to `output`, in order. A line is
+/// either plain query text or a JSON object with the query and its options;
+/// blank lines and lines starting with `#` are skipped. A line that cannot be
+/// run is reported as `{"line": N, "error": "..."}` and the batch goes on.
+/// Returns the number of such lines.
+pub fn run(
+    library: &Library,
+    index: &Index,
+    options: &Options,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> io::Result<usize> {
+    let mut errors = 0;
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let json = match run_line(line, library, index, options) {
+            Ok((id, results)) => serde_json::to_string(&BatchResult {
+                id: id.as_ref(),
+                results: &results,
+            })?,
+            Err(error) => {
+                errors += 1;
+                serde_json::to_string(&BatchError {
+                    line: i + 1,
+                    error: &error,
+                })?
+            }
+        };
+        writeln!(output, "{}", json)?;
+    }
+    Ok(errors)
+}
+
+/// Runs one line of a batch, returning the `id` given with it and its results.
+fn run_line(
+    line: &str,
+    library: &Library,
+    index: &Index,
+    options: &Options,
+) -> Result<(Option<Value>, SearchResults), String> {
+    let batch_query = BatchQuery::parse(line)?;
+    let query = batch_query.to_query(options)?;
+    Ok((batch_query.id, index.search(library, &query)))
+}
+
+impl BatchQuery {
+    /// Reads a JSON line, or takes any other line as the query text.
+    fn parse(line: &str) -> Result<BatchQuery, String> {
+        if line.starts_with('{') {
+            return serde_json::from_str(line).map_err(|err| err.to_string());
+        }
+        Ok(BatchQuery {
+            query: line.to_string(),
+            ..BatchQuery::default()
+        })
+    }
+
+    /// The query to run, or why its options are invalid.
+    pub fn to_query(&self, options: &Options) -> Result<Query, String> {
+        let mut query = Query::new(self.query.as_str())
+            .page(self.page(options)?)
+            .snippets(self.snippet_options(options))
+            .facets(true);
+        for (field, value) in self.filters()? {
+            query = query.filter(field, value);
+        }
+        Ok(query)
+    }
+
+    /// The page asked for. A line giving a `limit` but no `page` or `offset`
+    /// gets the page numbered by `--page` with that limit, so its pages line
+    /// up with the command line's; an `--offset` is kept as it is.
+    fn page(&self, options: &Options) -> Result<Page, String> {
+        let limit = match self.limit {
+            Some(0) => return Err("limit must be at least 1".to_string()),
+            Some(limit) => limit,
+            None => options.page().limit,
+        };
+        let number = match (self.offset, self.page) {
+            (Some(_), Some(_)) => return Err("page and offset cannot be combined".to_string()),
+            (Some(offset), None) => return Ok(Page { offset, limit }),
+            (None, Some(0)) => return Err("page must be at least 1".to_string()),
+            (None, Some(page)) => page,
+            (None, None) => match (options.offset, options.page) {
+                (None, Some(page)) => page,
+                (offset, _) => {
+                    let offset = offset.unwrap_or(0);
+                    return Ok(Page { offset, limit });
+                }
+            },
+        };
+        Page::numbered(number, limit).ok_or_else(|| {
+            format!(
+                "page {} is too large for {} results per page",
+                number, limit
+            )
+        })
+    }
+
+    fn filters(&self) -> Result<Vec<(Field, String)>, String> {
+        self.filters
+            .iter()
+            .map(|(name, value)| {
+                Field::from_name(name)
+                    .filter(|field| Field::FACETS.contains(field))
+                    .map(|field| (field, value.clone()))
+                    .ok_or_else(|| format!("cannot filter by `{}`", name))
+            })
+            .collect()
+    }
+
+    fn snippet_options(&self, options: &Options) -> SnippetOptions {
+        let defaults = options.snippet_options();
+        SnippetOptions {
+            max_snippets: self.max_snippets.unwrap_or(defaults.max_snippets),
+            sentences: self.sentences.unwrap_or(defaults.sentences),
+            ..defaults
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_args;
+    use crate::testing::library;
+
+    /// Runs `input` as a batch with two results per page, returning the
+    /// number of errors and every output line.
+    fn run_batch(input: &str) -> (usize, Vec<Value>) {
+        run_batch_with(&["--limit", "2"], input)
+    }
+
+    /// Runs `input` as a batch with the command line options `args`.
+    fn run_batch_with(args: &[&str], input: &str) -> (usize, Vec<Value>) {
+        let library = library();
+        let index = Index::new(&library);
+        let options = parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        let mut output = Vec::new();
+        let errors = run(&library, &index, &options, input.as_bytes(), &mut output).unwrap();
+        let lines = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (errors, lines)
+    }
+
+    fn names(results: &Value) -> Vec<&str> {
+        let hits = results["hits"].as_array().unwrap();
+        hits.iter()
+            .map(|hit| hit["name"].as_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn every_query_gives_one_line_in_order() {
+        let (errors, lines) = run_batch("ring\n\n# a comment\n   \none\ngold\n");
+        assert_eq!(errors, 0);
+        assert_eq!(lines.len(), 3, "blank lines and comments are skipped");
+        assert_eq!(lines[0]["query"], "ring");
+        assert_eq!(lines[0]["total_hits"], 3);
+        assert_eq!(lines[0]["hits"].as_array().unwrap().len(), 2);
+        assert_eq!(names(&lines[1]), ["Chapter 2"]);
+        assert_eq!(lines[2]["query"], "gold");
+        assert!(lines[0].get("id").is_none());
+    }
+
+    #[test]
+    fn json_lines_override_the_command_line_options() {
+        let (errors, lines) = run_batch(
+            r#"{"id": 7, "query": "ring", "limit": 1, "page": 3}
+{"id": "b", "query": "ring", "offset": 1}
+{"query": "ring", "filters": {"subject": "Bible"}}
+{"query": "ring", "max_snippets": 0}"#,
+        );
+        assert_eq!(errors, 0);
+        assert_eq!(lines[0]["id"], 7);
+        assert_eq!(lines[0]["offset"], 2);
+        assert_eq!(lines[0]["hits"].as_array().unwrap().len(), 1);
+        assert_eq!(lines[1]["id"], "b");
+        assert_eq!(lines[1]["offset"], 1);
+        assert_eq!(lines[1]["hits"].as_array().unwrap().len(), 2);
+        assert_eq!(lines[2]["total_hits"], 2);
+        let mut bible = names(&lines[2]);
+        bible.sort();
+        assert_eq!(bible, ["Job 42", "Numbers 6"]);
+        let snippets = lines[3]["hits"][0]["snippets"].as_array().unwrap();
+        assert!(snippets.is_empty());
+    }
+
+    #[test]
+    fn command_line_pages_are_numbered_with_the_limit_of_the_line() {
+        let (errors, lines) = run_batch_with(
+            &["--limit", "2", "--page", "2"],
+            r#"ring
+{"query": "ring", "limit": 1}
+{"query": "ring", "limit": 1, "page": 1}"#,
+        );
+        assert_eq!(errors, 0);
+        assert_eq!(lines[0]["offset"], 2);
+        assert_eq!(lines[1]["offset"], 1);
+        assert_eq!(lines[2]["offset"], 0);
+
+        let (_, lines) = run_batch_with(&["--offset", "1"], r#"{"query": "ring", "limit": 1}"#);
+        assert_eq!(lines[0]["offset"], 1);
+    }
+
+    #[test]
+    fn bad_lines_are_reported_and_the_batch_goes_on() {
+        let (errors, lines) = run_batch(
+            r#"{"query": "ring", "page": 1, "offset": 0}
+{"query": "ring", "page": 0}
+{"query": "ring", "limit": 0}
+{"query": "ring", "filters": {"content": "gold"}}
+{"query": "ring", "colour": "red"}
+{"query": "ring"
+ring"#,
+        );
+        assert_eq!(errors, 6);
+        for (i, line) in lines.iter().take(6).enumerate() {
+            assert_eq!(line["line"], i + 1);
+            assert!(line["error"].is_string(), "{}", line);
+        }
+        assert_eq!(lines[0]["error"], "page and offset cannot be combined");
+        assert_eq!(lines[3]["error"], "cannot filter by `content`");
+        assert_eq!(lines[6]["total_hits"], 3);
+    }
+
+    #[test]
+    fn overflowing_pages_are_reported() {
+        let (errors, lines) = run_batch(
+            r#"{"query": "ring", "page": 4611686018427387904, "limit": 8}
+{"query": "ring", "limit": 1000000000000000000}
+{"query": "ring", "offset": 18446744073709551615}"#,
+        );
+        assert_eq!(errors, 1);
+        assert_eq!(lines[0]["line"], 1);
+        assert_eq!(lines[1]["hits"].as_array().unwrap().len(), 3);
+        assert!(lines[2]["hits"].as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+use std::time::{Duration, Instant};
+
+use librarian::query::parse_query;
+use librarian::scoring::Ranking;
+use librarian::search::{search, search_exhaustive, search_faceted, Page, TopDocs};
+use librarian::{Index, Library};
+
+const VOCABULARY: usize = 5000;
+const SUBJECTS: &[&str] = &["History", "Poetry", "Science", "Travel", "Law"];
+const REPEATS: usize = 5;
+
+/// Queries mixing frequent (`w1`) and rare (`w900`) words, a phrase and a
+/// subject filter, over the Zipf distributed vocabulary of `synthetic_library`.
+const QUERIES: &[&str] = &[
+    "w3",
+    "w2 w40",
+    "w5 w900 w2500",
+    "w1 w2 w3 w4 w5",
+    "w10 w200 w3000 w4000",
+    "\"w1 w2\" w70",
+    "subject:Poetry w7 w300",
+];
+
+/// Compares MaxScore evaluation against exhaustive scoring on a synthetic
+/// library of `docs` documents and prints the timings of every query, along
+/// with MaxScore plus the facet counting pass of `search_faceted`.
+pub fn run(docs: usize, page: Page) {
+    let library = synthetic_library(docs, VOCABULARY);
+    let start = Instant::now();
+    let index = Index::new(&library);
+    let index = index.inverted();
+    println!(
+        "Indexed {} synthetic documents in {:.1} ms, top {} results",
+        docs,
+        millis(start.elapsed()),
+        page.end()
+    );
+
+    let ranking = Ranking::default();
+    println!(
+        "{:<28} {:>9} {:>14} {:>14} {:>8} {:>11}  result",
+        "query", "matches", "exhaustive ms", "maxscore ms", "speedup", "faceted ms"
+    );
+    for &text in QUERIES {
+        let query = parse_query(text);
+        let (exhaustive, exhaustive_time) =
+            time(|| search_exhaustive(index, &query, &ranking, page));
+        let (pruned, pruned_time) = time(|| search(index, &query, &ranking, page));
+        let (faceted, faceted_time) = time(|| search_faceted(index, &query, &ranking, page));
+
+        println!(
+            "{:<28} {:>9} {:>14.2} {:>14.2} {:>7.1}x {:>11.2}  {}",
+            text,
+            exhaustive.total_hits,
+            millis(exhaustive_time),
+            millis(pruned_time),
+            exhaustive_time.as_secs_f64() / pruned_time.as_secs_f64().max(1e-9),
+            millis(faceted_time),
+            if same_ranking(&exhaustive, &pruned) && same_ranking(&exhaustive, &faceted) {
+                "ok"
+            } else {
+                "MISMATCH"
+            }
+        );
+    }
+}
+
+/// Runs `f` `REPEATS` times, returning its last result and the fastest time.
+fn time<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..REPEATS {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (result.unwrap(), best)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Both searches must return the same scores in the same order. Documents
+/// with tied scores may legitimately differ, so only scores are compared.
+fn same_ranking(a: &TopDocs, b: &TopDocs) -> bool {
+    a.docs.len() == b.docs.len()
+        && a.docs
+            .iter()
+            .zip(&b.docs)
+            .all(|(a, b)| (a.score - b.score).abs() <= 1e-4 * a.score.abs().max(1.0))
+}
+
+/// Generates `docs` documents whose words, `w1` to `w<vocabulary>`, follow a
+/// Zipf distribution, so a few words are very common and most are rare, as in
+/// natural text.
+fn synthetic_library(docs: usize, vocabulary: usize) -> Library {
+    let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+    let cumulative: Vec<f64> = (1..=vocabulary)
+        .scan(0.0, |sum, rank| {
+            *sum += 1.0 / rank as f64;
+            Some(*sum)
+        })
+        .collect();
+    let total = cumulative[vocabulary - 1];
+    let words = |rng: &mut XorShift, count: usize| -> String {
+        let words: Vec<String> = (0..count)
+            .map(|_| {
+                let target = rng.unit() * total;
+                let rank = cumulative.partition_point(|&c| c < target) + 1;
+                format!("w{}", rank)
+            })
+            .collect();
+        words.join(" ")
+    };
+
+    let mut library = Library::new();
+    for _ in 0..docs {
+        let name_length = 1 + rng.below(3);
+        let content_length = 20 + rng.below(180);
+        let name = words(&mut rng, name_length);
+        let subject = SUBJECTS[rng.below(SUBJECTS.len())];
+        let content = words(&mut rng, content_length);
+        library.add(&name, subject, &content);
+    }
+    library
+}
+
+/// Small deterministic PRNG, so every run benchmarks the same library.
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librarian::query::Query;
+    use librarian::scoring::TfIdf;
+    use librarian::search::Field;
+    use librarian::thesaurus::{add_entry, expand_synonyms, Thesaurus};
+
+    /// Small enough that queries match many documents with a wide spread of
+    /// scores.
+    fn library() -> Library {
+        synthetic_library(400, 40)
+    }
+
+    fn assert_same_ranking(pruned: &TopDocs, exhaustive: &TopDocs, context: &str) {
+        assert!(
+            same_ranking(pruned, exhaustive),
+            "{}: {:?} != {:?}",
+            context,
+            pruned.docs,
+            exhaustive.docs
+        );
+        if pruned.total_hits_exact {
+            assert_eq!(pruned.total_hits, exhaustive.total_hits, "{}", context);
+        } else {
+            assert!(pruned.total_hits <= exhaustive.total_hits, "{}", context);
+        }
+    }
+
+    /// Runs `query` with MaxScore and exhaustively for pages of 1 result, of
+    /// exactly the number of matches, of more than that and a few offsets.
+    /// Returns whether MaxScore skipped documents for any of them.
+    fn check(query: &Query, ranking: &Ranking, context: &str) -> bool {
+        let index = Index::new(&library());
+        let index = index.inverted();
+        let all = search_exhaustive(index, query, ranking, Page::default());
+        assert!(all.total_hits > 0, "{}: no matches", context);
+
+        let matches = all.total_hits;
+        let mut pruned_any = false;
+        for (offset, limit) in [
+            (0, 1),
+            (0, matches),
+            (0, matches + 5),
+            (3, 7),
+            (matches - 1, 1),
+        ] {
+            let page = Page { offset, limit };
+            let pruned = search(index, query, ranking, page);
+            let exhaustive = search_exhaustive(index, query, ranking, page);
+            assert_same_ranking(&pruned, &exhaustive, &format!("{} {:?}", context, page));
+            pruned_any |= !pruned.total_hits_exact;
+        }
+        pruned_any
+    }
+
+    fn thesaurus(lines: &[&str]) -> Thesaurus {
+        let mut thesaurus = Thesaurus::new();
+        for line in lines {
+            add_entry(&mut thesaurus, line, false).unwrap();
+        }
+        thesaurus
+    }
+
+    #[test]
+    fn synthetic_library_is_deterministic_and_skewed() {
+        assert_eq!(library(), library());
+        let index = Index::new(&library());
+        let index = index.inverted();
+        let common = index.postings(Field::Content, "w1").len();
+        let rare = index.postings(Field::Content, "w40").len();
+        assert!(common > 2 * rare, "{} vs {}", common, rare);
+    }
+
+    #[test]
+    fn terms_match_exhaustive_evaluation() {
+        let mut pruned = false;
+        for text in ["w1", "w4", "w2 w18", "w1 w2 w3 w4 w5", "w6 w26 w36"] {
+            pruned |= check(&parse_query(text), &Ranking::default(), text);
+        }
+        assert!(pruned, "no query was pruned");
+    }
+
+    #[test]
+    fn phrases_match_exhaustive_evaluation() {
+        for text in ["\"w1 w2\"", "\"w2 w1\" w10", "w3 \"w1 w1\" w31"] {
+            check(&parse_query(text), &Ranking::default(), text);
+        }
+    }
+
+    #[test]
+    fn field_scoped_clauses_match_exhaustive_evaluation() {
+        for text in [
+            "name:w1",
+            "name:w2 w3",
+            "subject:poetry w1 w7",
+            "content:\"w1 w2\" name:w3",
+            "name:w1 subject:history",
+        ] {
+            check(&parse_query(text), &Ranking::default(), text);
+        }
+    }
+
+    #[test]
+    fn synonyms_match_exhaustive_evaluation() {
+        let thesaurus = thesaurus(&["w5, w21, w1 w2", "w8 => w3"]);
+        for text in ["w5", "w8 w12", "w4 w21", "name:w5 w1"] {
+            let query = expand_synonyms(&parse_query(text), &thesaurus);
+            check(&query, &Ranking::default(), text);
+        }
+    }
+
+    #[test]
+    fn boosts_match_exhaustive_evaluation() {
+        let mut ranking = Ranking::default();
+        ranking.boost_field(Field::Content, 0.5).unwrap();
+        ranking.boost_field(Field::Name, 4.0).unwrap();
+        ranking.boost_subject("Poetry", 3.0).unwrap();
+        ranking.boost_subject("Science", 0.2).unwrap();
+        for text in ["w1", "w3 w10", "\"w1 w2\" w6", "subject:history w4"] {
+            check(&parse_query(text), &ranking, text);
+        }
+
+        ranking.scorer = Box::new(TfIdf);
+        check(&parse_query("w2 w9 w16"), &ranking, "tfidf");
+    }
+
+    #[test]
+    fn filters_match_exhaustive_evaluation() {
+        let thesaurus = thesaurus(&["w5, w21"]);
+        for text in ["w1 w13", "w5", "name:w2 w4"] {
+            let mut query = expand_synonyms(&parse_query(text), &thesaurus);
+            query.filters.push((Field::Subject, "poetry".to_string()));
+            check(&query, &Ranking::default(), text);
+        }
+    }
+}
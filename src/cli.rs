@@ -0,0 +1,424 @@
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use librarian::render::{Highlight, Style};
+use librarian::results::SnippetOptions;
+use librarian::scoring::{scorer_by_name, Ranking, SCORERS};
+use librarian::search::{Field, Page};
+use librarian::thesaurus::ThesaurusFormat;
+
+pub const USAGE: &str = "\
+Usage: librarian [COMMAND] [OPTIONS]
+
+Commands:
+  search                     Search interactively; type :help at the prompt for
+                             commands (default)
+  tui                        Browse results in a full-screen terminal interface
+  batch                      Run queries read one per line, as text or JSON, and
+                             write their results as JSON Lines
+  serve                      Serve search, document retrieval, ingest and delete
+                             as a JSON API on localhost
+  bench                      Time MaxScore against exhaustive evaluation on a
+                             synthetic library
+
+Options:
+  --thesaurus <PATH>         Synonyms file to load (default: thesaurus.txt, if present)
+  --thesaurus-format <FMT>   native, solr or wordnet (default: native, wordnet for .pl files)
+  --scorer <NAME>            Ranking function: bm25 or tfidf (default: bm25)
+  --boost <FIELD=WEIGHT>     Multiply scores of matches in name, subject or content
+                             (default: name=2); may be repeated
+  --subject-boost <SUBJECT=WEIGHT>
+                             Multiply scores of documents with that subject; may be repeated
+  --limit <N>                Results per page (default: 10)
+  --page <N>                 Page of results to show, starting at 1
+  --offset <N>               Number of results to skip, instead of --page
+  --format <FMT>             text, plain, json, jsonl or html (default: text); all but
+                             text read the query from stdin without prompting
+  --color <WHEN>             Colour matches: auto, always or never (default: auto,
+                             which colours only terminals and honours NO_COLOR)
+  --highlight <STYLE>        Style of matched words, e.g. bold+red (default: red)
+  --synonym-highlight <STYLE>
+                             Style of words matched through synonyms (default: yellow)
+  --snippet-words <N>        Most words in a content snippet (default: 30)
+  --snippet-chars <N>        Most characters in a content snippet (default: 200)
+  --max-snippets <N>         Most content snippets per document (default: 3)
+  --sentences                Show every matching sentence in full instead of
+                             the best passages
+  --explain                  Show how the query was analyzed and why each result
+                             matched, with its score breakdown; interactive
+                             search only
+  --input <PATH>             File of queries for batch (default: stdin)
+  --port <N>                 Port for serve, 0 for any free one (default: 8080)
+  --docs <N>                 Size of the synthetic library for bench (default: 20000)
+  -h, --help                 Print this help";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Coloured results followed by interactive prompts.
+    #[default]
+    Text,
+    /// The same results without colours or prompts.
+    Plain,
+    /// One JSON object with the results, facets and hit count.
+    Json,
+    /// One JSON object per result and line.
+    JsonLines,
+    /// An HTML fragment with matches in `<mark>` tags.
+    Html,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "jsonl" => Some(OutputFormat::JsonLines),
+            "plain" => Some(OutputFormat::Plain),
+            "html" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Search,
+    Tui,
+    Batch,
+    Serve,
+    Bench,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub command: Command,
+    pub thesaurus: Option<PathBuf>,
+    pub thesaurus_format: Option<ThesaurusFormat>,
+    pub scorer: Option<String>,
+    pub field_boosts: Vec<(Field, f32)>,
+    pub subject_boosts: Vec<(String, f32)>,
+    pub limit: Option<usize>,
+    pub page: Option<usize>,
+    pub offset: Option<usize>,
+    pub snippet_words: Option<usize>,
+    pub snippet_chars: Option<usize>,
+    pub max_snippets: Option<usize>,
+    pub sentences: bool,
+    pub explain: bool,
+    pub format: OutputFormat,
+    pub color: ColorChoice,
+    pub highlight: Option<Style>,
+    pub synonym_highlight: Option<Style>,
+    pub input: Option<PathBuf>,
+    pub port: Option<u16>,
+    pub docs: Option<usize>,
+    pub help: bool,
+}
+
+impl Options {
+    /// The thesaurus format to use, falling back to the file extension.
+    pub fn thesaurus_format(&self) -> ThesaurusFormat {
+        self.thesaurus_format.unwrap_or_else(|| {
+            match self.thesaurus.as_ref().and_then(|path| path.extension()) {
+                Some(ext) if ext == "pl" => ThesaurusFormat::WordNet,
+                _ => ThesaurusFormat::Native,
+            }
+        })
+    }
+
+    /// The slice of results selected by `--limit` and `--page` or `--offset`.
+    pub fn page(&self) -> Page {
+        let limit = self.limit.unwrap_or(Page::default().limit);
+        match (self.offset, self.page) {
+            (Some(offset), _) => Page { offset, limit },
+            (None, Some(page)) => Page::numbered(page, limit).expect("checked by parse_args"),
+            (None, None) => Page { offset: 0, limit },
+        }
+    }
+
+    /// The snippet limits set by `--snippet-words`, `--snippet-chars` and
+    /// `--max-snippets`, and whether `--sentences` asked for whole sentences.
+    pub fn snippet_options(&self) -> SnippetOptions {
+        let defaults = SnippetOptions::default();
+        SnippetOptions {
+            max_words: self.snippet_words.unwrap_or(defaults.max_words),
+            max_chars: self.snippet_chars.unwrap_or(defaults.max_chars),
+            max_snippets: self.max_snippets.unwrap_or(defaults.max_snippets),
+            sentences: self.sentences,
+        }
+    }
+
+    /// The match styles, or `None` if output should not be coloured.
+    pub fn highlight(&self) -> Option<Highlight> {
+        self.highlight_for(io::stdout().is_terminal(), env::var_os("NO_COLOR"))
+    }
+
+    /// `highlight` for output that does or does not go to a terminal, with
+    /// the given value of `NO_COLOR`.
+    fn highlight_for(&self, terminal: bool, no_color: Option<OsString>) -> Option<Highlight> {
+        let color = match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => terminal && no_color.is_none_or(|value| value.is_empty()),
+        };
+        if !color {
+            return None;
+        }
+        let mut highlight = Highlight::default();
+        if let Some(style) = &self.highlight {
+            highlight.direct = style.clone();
+        }
+        if let Some(style) = &self.synonym_highlight {
+            highlight.synonym = style.clone();
+        }
+        Some(highlight)
+    }
+
+    /// The ranking configured by `--scorer`, `--boost` and `--subject-boost`.
+    pub fn ranking(&self) -> Ranking {
+        let mut ranking = Ranking::default();
+        if let Some(scorer) = self.scorer.as_deref().and_then(scorer_by_name) {
+            ranking.scorer = scorer;
+        }
+        for &(field, boost) in &self.field_boosts {
+            ranking
+                .boost_field(field, boost)
+                .expect("boosts are checked by parse_weight");
+        }
+        for (subject, boost) in &self.subject_boosts {
+            ranking
+                .boost_subject(subject, *boost)
+                .expect("boosts are checked by parse_weight");
+        }
+        ranking
+    }
+}
+
+/// Parses command line arguments (without the program name). Values may be
+/// given as `--flag value` or `--flag=value`.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut command_seen = false;
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+
+        // Only the first positional argument may name the command
+        let is_command = !command_seen && !flag.starts_with('-');
+        command_seen |= is_command;
+        match flag.as_str() {
+            "--thesaurus" => options.thesaurus = Some(PathBuf::from(value()?)),
+            "--thesaurus-format" => {
+                let name = value()?;
+                let format = ThesaurusFormat::from_name(&name)
+                    .ok_or_else(|| format!("unknown thesaurus format `{}`", name))?;
+                options.thesaurus_format = Some(format);
+            }
+            "--scorer" => {
+                let name = value()?;
+                if scorer_by_name(&name).is_none() {
+                    return Err(format!(
+                        "unknown scorer `{}`, expected one of: {}",
+                        name,
+                        SCORERS.join(", ")
+                    ));
+                }
+                options.scorer = Some(name);
+            }
+            "--boost" => {
+                let (name, boost) = parse_weight(&value()?)?;
+                let field = Field::from_name(&name)
+                    .ok_or_else(|| format!("unknown field `{}` in --boost", name))?;
+                options.field_boosts.push((field, boost));
+            }
+            "--subject-boost" => options.subject_boosts.push(parse_weight(&value()?)?),
+            "--limit" => options.limit = Some(parse_count(&flag, &value()?, 1)?),
+            "--page" => options.page = Some(parse_count(&flag, &value()?, 1)?),
+            "--offset" => options.offset = Some(parse_count(&flag, &value()?, 0)?),
+            "--input" => options.input = Some(PathBuf::from(value()?)),
+            "--port" => {
+                let port = value()?;
+                let port = port
+                    .parse()
+                    .map_err(|_| format!("--port expects a port number, got `{}`", port))?;
+                options.port = Some(port);
+            }
+            "--docs" => options.docs = Some(parse_count(&flag, &value()?, 1)?),
+            "--snippet-words" => options.snippet_words = Some(parse_count(&flag, &value()?, 1)?),
+            "--snippet-chars" => options.snippet_chars = Some(parse_count(&flag, &value()?, 1)?),
+            "--max-snippets" => options.max_snippets = Some(parse_count(&flag, &value()?, 0)?),
+            "--sentences" => options.sentences = true,
+            "--explain" => options.explain = true,
+            "--color" => {
+                options.color = match value()?.to_ascii_lowercase().as_str() {
+                    "auto" => ColorChoice::Auto,
+                    "always" => ColorChoice::Always,
+                    "never" => ColorChoice::Never,
+                    other => return Err(format!("unknown --color value `{}`", other)),
+                }
+            }
+            "--highlight" => options.highlight = Some(Style::parse(&value()?)?),
+            "--synonym-highlight" => options.synonym_highlight = Some(Style::parse(&value()?)?),
+            "--format" => {
+                let name = value()?;
+                options.format = OutputFormat::from_name(&name)
+                    .ok_or_else(|| format!("unknown output format `{}`", name))?;
+            }
+            "-h" | "--help" => options.help = true,
+            "search" if is_command => options.command = Command::Search,
+            "tui" if is_command => options.command = Command::Tui,
+            "batch" if is_command => options.command = Command::Batch,
+            "serve" if is_command => options.command = Command::Serve,
+            "bench" if is_command => options.command = Command::Bench,
+            _ => return Err(format!("unexpected argument `{}`", flag)),
+        }
+    }
+
+    if options.page.is_some() && options.offset.is_some() {
+        return Err("--page and --offset cannot be combined".to_string());
+    }
+    if options.explain {
+        let other = match (options.command, options.format) {
+            (Command::Search, OutputFormat::Text) => None,
+            (Command::Search, _) => Some("--format"),
+            (Command::Tui, _) => Some("the tui command"),
+            (Command::Batch, _) => Some("the batch command"),
+            (Command::Serve, _) => Some("the serve command"),
+            (Command::Bench, _) => Some("the bench command"),
+        };
+        if let Some(other) = other {
+            return Err(format!(
+                "--explain is only shown by the interactive search, not with {}",
+                other
+            ));
+        }
+    }
+    if let Some(page) = options.page {
+        let limit = options.limit.unwrap_or(Page::default().limit);
+        if Page::numbered(page, limit).is_none() {
+            return Err(format!(
+                "--page {} is too large for {} results per page",
+                page, limit
+            ));
+        }
+    }
+    Ok(options)
+}
+
+/// Parses a whole number of at least `min`.
+fn parse_count(flag: &str, value: &str, min: usize) -> Result<usize, String> {
+    match value.parse() {
+        Ok(count) if count >= min => Ok(count),
+        _ => Err(format!(
+            "{} expects a number of at least {}, got `{}`",
+            flag, min, value
+        )),
+    }
+}
+
+/// Parses `NAME=WEIGHT`, where the weight is a finite number of at least 0.
+fn parse_weight(arg: &str) -> Result<(String, f32), String> {
+    let (name, weight) = arg
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected NAME=WEIGHT, got `{}`", arg))?;
+    let weight = weight
+        .parse()
+        .ok()
+        .filter(|weight: &f32| weight.is_finite() && *weight >= 0.0)
+        .ok_or_else(|| {
+            format!(
+                "invalid weight `{}` in `{}`, expected a finite number of at least 0",
+                weight, arg
+            )
+        })?;
+    Ok((name.to_string(), weight))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn auto_colour_needs_a_terminal_and_no_no_color() {
+        let options = parse(&[]).unwrap();
+        assert!(options.highlight_for(true, None).is_some());
+        assert!(options.highlight_for(false, None).is_none());
+        assert!(options
+            .highlight_for(true, Some(OsString::from("1")))
+            .is_none());
+        assert!(
+            options.highlight_for(true, Some(OsString::new())).is_some(),
+            "an empty NO_COLOR is ignored"
+        );
+    }
+
+    #[test]
+    fn color_always_and_never_override_the_environment() {
+        let always = parse(&["--color", "always"]).unwrap();
+        assert!(always
+            .highlight_for(false, Some(OsString::from("1")))
+            .is_some());
+        let never = parse(&["--color", "NEVER"]).unwrap();
+        assert!(never.highlight_for(true, None).is_none());
+        assert!(parse(&["--color", "sometimes"]).is_err());
+    }
+
+    #[test]
+    fn highlight_options_replace_the_default_styles() {
+        let options = parse(&[
+            "--color",
+            "always",
+            "--highlight",
+            "bold+green",
+            "--synonym-highlight",
+            "4;36",
+        ])
+        .unwrap();
+        let highlight = options.highlight_for(false, None).unwrap();
+        assert_eq!(highlight.direct, Style::parse("1;32").unwrap());
+        assert_eq!(highlight.synonym, Style::parse("underline cyan").unwrap());
+        assert!(parse(&["--highlight", "sparkly"]).is_err());
+    }
+
+    #[test]
+    fn explain_is_only_for_the_interactive_search() {
+        assert!(parse(&["--explain"]).unwrap().explain);
+        for args in [
+            &["--explain", "--format", "json"][..],
+            &["tui", "--explain"],
+            &["batch", "--explain"],
+            &["serve", "--explain"],
+            &["bench", "--explain"],
+        ] {
+            let err = parse(args).unwrap_err();
+            assert!(err.starts_with("--explain is only shown"), "{}", err);
+        }
+    }
+}
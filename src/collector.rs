@@ -0,0 +1,97 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// Collects the `k` best scoring documents in a min-heap, so a new document
+/// only has to beat the weakest one kept so far.
+pub struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Entry>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    score: f32,
+    doc_id: usize,
+}
+
+impl Eq for Entry {}
+
+impl Ord for Entry {
+    /// Higher scores are better; ties go to the lower document id.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then(other.doc_id.cmp(&self.doc_id))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl TopK {
+    /// The heap is allocated up front, so `k` should be no more than the
+    /// number of documents that can be pushed.
+    pub fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k.saturating_add(1)),
+        }
+    }
+
+    pub fn push(&mut self, doc_id: usize, score: f32) {
+        let entry = Entry { score, doc_id };
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(entry));
+        } else if self.heap.peek().is_some_and(|Reverse(min)| entry > *min) {
+            self.heap.pop();
+            self.heap.push(Reverse(entry));
+        }
+    }
+
+    /// The score a document must beat to be collected, once `k` are held.
+    pub fn threshold(&self) -> Option<f32> {
+        if self.heap.len() < self.k {
+            return None;
+        }
+        self.heap.peek().map(|Reverse(min)| min.score)
+    }
+
+    /// The collected `(doc_id, score)` pairs, best first.
+    pub fn into_sorted(self) -> Vec<(usize, f32)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(entry)| (entry.doc_id, entry.score))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(k: usize, docs: &[(usize, f32)]) -> Vec<(usize, f32)> {
+        let mut top = TopK::new(k);
+        for &(doc_id, score) in docs {
+            top.push(doc_id, score);
+        }
+        top.into_sorted()
+    }
+
+    #[test]
+    fn keeps_the_best_k_best_first() {
+        let docs = [(0, 1.0), (1, 4.0), (2, 2.0), (3, 3.0), (4, 0.5)];
+        assert_eq!(collect(3, &docs), [(1, 4.0), (3, 3.0), (2, 2.0)]);
+        assert_eq!(collect(10, &docs).len(), 5);
+        assert!(collect(0, &docs).is_empty());
+    }
+
+    #[test]
+    fn ties_go_to_the_lower_document_id() {
+        let docs = [(5, 1.0), (2, 1.0), (7, 1.0), (3, 1.0)];
+        assert_eq!(collect(2, &docs), [(2, 1.0), (3, 1.0)]);
+    }
+}
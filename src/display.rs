@@ -0,0 +1,60 @@
+use librarian::explain::analysis_steps;
+use librarian::highlighter::Highlighter;
+use librarian::query::{split_clauses, Query};
+use librarian::render::{highlight_text, sanitize_line, sanitize_text, Highlight};
+use librarian::{Index, Library, SearchResults};
+
+/// A whole document, with matches coloured unless `highlight` is `None`.
+pub fn full_document(
+    library: &Library,
+    doc_id: usize,
+    highlighter: &Highlighter,
+    highlight: Option<&Highlight>,
+) -> String {
+    let doc = &library.documents()[doc_id];
+
+    let matches = highlighter.matches(&doc.content);
+    let highlighted_content = highlight_text(&doc.content, &matches, highlight);
+
+    format!(
+        "Document: {}\nSubject: {}\nContent:\n{}\n",
+        sanitize_line(&doc.name),
+        sanitize_line(&doc.subject),
+        highlighted_content
+    )
+}
+
+/// Prints how every clause of the query text was analyzed, and the query
+/// that was run after synonym expansion.
+pub fn display_query_analysis(input: &str, query: &Query) {
+    println!("Query analysis:");
+    for (field, text, _) in split_clauses(input) {
+        println!(
+            "  {} (in {}):",
+            text,
+            field.map_or("any field", |f| f.name())
+        );
+        for (step, tokens) in analysis_steps(text) {
+            let tokens = if tokens.is_empty() {
+                "(nothing left)".to_string()
+            } else {
+                tokens.join(" ")
+            };
+            println!("    {}: {}", step, tokens);
+        }
+    }
+    println!("  with synonyms: {}", query);
+}
+
+/// Prints why every shown result matched and how its score was computed.
+pub fn display_explanations(index: &Index, query: &Query, results: &SearchResults) {
+    for hit in &results.hits {
+        println!(
+            "Explanation for {}. {}:",
+            hit.rank,
+            sanitize_line(&hit.name)
+        );
+        let explanation = index.explain(query, hit.id).to_string();
+        print!("{}", sanitize_text(&explanation));
+    }
+}
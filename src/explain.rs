@@ -0,0 +1,298 @@
+use std::fmt;
+
+use crate::query::{Query, QueryNode};
+use crate::scoring::Ranking;
+use crate::search::{
+    clause_fields, doc_positions, phrase_occurrences, phrase_positions, Field, InvertedIndex,
+};
+use crate::utils::{is_stop_word, split_word};
+
+/// Why a document got its score: a value, what it stands for, and the values
+/// it was computed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub value: f32,
+    pub description: String,
+    pub details: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn new(value: f32, description: String, details: Vec<Explanation>) -> Self {
+        Explanation {
+            value,
+            description,
+            details,
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{:.3} = {}",
+            "",
+            self.value,
+            self.description,
+            indent = depth * 2
+        )?;
+        for detail in &self.details {
+            detail.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Renders the explanation as an indented tree, one value per line.
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Explains the score `search` gives document `doc_id` for `query`: which
+/// clauses, terms and synonyms matched in which fields and at which positions,
+/// and how each match was scored and weighted.
+pub fn explain(
+    index: &InvertedIndex,
+    query: &Query,
+    ranking: &Ranking,
+    doc_id: usize,
+) -> Explanation {
+    let clauses: Vec<Explanation> = query
+        .clauses
+        .iter()
+        .filter_map(|clause| {
+            let fields: Vec<Explanation> = clause_fields(clause)
+                .into_iter()
+                .filter_map(|field| {
+                    let weight = ranking.field_boost(field);
+                    explain_node(index, field, &clause.node, weight, false, ranking, doc_id)
+                })
+                .collect();
+            if fields.is_empty() {
+                return None;
+            }
+            let value = fields.iter().map(|e| e.value).sum();
+            Some(Explanation::new(
+                value,
+                format!("clause {}", clause),
+                fields,
+            ))
+        })
+        .collect();
+
+    let subject = index.field_value(Field::Subject, doc_id);
+    let subject_boost = ranking.subject_boost(subject);
+    let sum: f32 = clauses.iter().map(|e| e.value).sum();
+    Explanation::new(
+        sum * subject_boost,
+        format!(
+            "sum of {} matching clauses, times subject boost {} ({})",
+            clauses.len(),
+            subject_boost,
+            subject
+        ),
+        clauses,
+    )
+}
+
+/// Explains how `node` matched in `field`, or `None` if it did not. Synonym
+/// expansions are the alternatives of an OR-group after the first.
+fn explain_node(
+    index: &InvertedIndex,
+    field: Field,
+    node: &QueryNode,
+    weight: f32,
+    synonym: bool,
+    ranking: &Ranking,
+    doc_id: usize,
+) -> Option<Explanation> {
+    let (positions, doc_freq) = match node {
+        QueryNode::Term(term) => (
+            doc_positions(index, field, term, doc_id).to_vec(),
+            index.postings(field, term).len(),
+        ),
+        QueryNode::Phrase(terms) => {
+            let starts = doc_positions(index, field, &terms[0].1, doc_id);
+            (
+                phrase_positions(index, field, terms, doc_id, starts),
+                phrase_occurrences(index, field, terms).count(),
+            )
+        }
+        QueryNode::Or(nodes) => {
+            let details: Vec<Explanation> = nodes
+                .iter()
+                .enumerate()
+                .filter_map(|(i, node)| {
+                    explain_node(
+                        index,
+                        field,
+                        node,
+                        weight,
+                        synonym || i > 0,
+                        ranking,
+                        doc_id,
+                    )
+                })
+                .collect();
+            if details.is_empty() {
+                return None;
+            }
+            let value = details.iter().map(|e| e.value).sum();
+            return Some(Explanation::new(value, format!("any of {}", node), details));
+        }
+        QueryNode::Boost(node, boost) => {
+            return explain_node(index, field, node, weight * boost, synonym, ranking, doc_id)
+        }
+    };
+    if positions.is_empty() {
+        return None;
+    }
+
+    let tf = positions.len();
+    let doc_length = index.field_length(field, doc_id);
+    let stats = index.field_stats(field);
+    let score = ranking.scorer.score(tf, doc_length, doc_freq, &stats);
+    let scored = Explanation::new(
+        score,
+        format!(
+            "score of tf {}, field length {}, found in {} of {} documents (average length {:.1})",
+            tf, doc_length, doc_freq, stats.doc_count, stats.avg_length
+        ),
+        Vec::new(),
+    );
+    Some(Explanation::new(
+        score * weight,
+        format!(
+            "{}{}:{} at positions {:?}, weight {}",
+            if synonym { "synonym " } else { "" },
+            field.name(),
+            node,
+            positions,
+            weight
+        ),
+        vec![scored],
+    ))
+}
+
+/// The tokens of `text` after each step of tokenizing, to show how a query
+/// was analyzed.
+pub fn analysis_steps(text: &str) -> Vec<(&'static str, Vec<String>)> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let parts: Vec<&str> = words.iter().flat_map(|word| split_word(word)).collect();
+    let lowercased: Vec<String> = parts.iter().map(|part| part.to_lowercase()).collect();
+    let kept: Vec<String> = lowercased
+        .iter()
+        .filter(|word| !is_stop_word(word))
+        .cloned()
+        .collect();
+    let owned = |tokens: Vec<&str>| tokens.into_iter().map(str::to_string).collect();
+    vec![
+        ("split on whitespace", owned(words)),
+        ("split on punctuation", owned(parts)),
+        ("lowercase", lowercased),
+        ("remove stop words", kept),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::{Document, Library};
+    use crate::query::parse_query;
+    use crate::search::{create_inverted_index, search, search_with_synonyms, Page};
+    use crate::thesaurus::{add_entry, Thesaurus};
+    use crate::utils::tokenize;
+
+    fn index() -> InvertedIndex {
+        let mut library = Library::new();
+        for (name, subject, content) in [
+            (
+                "Chapter 2",
+                "Lord of the Rings",
+                "The One Ring, the ring of power",
+            ),
+            ("Numbers 6", "Bible", "A gold band and a ring"),
+            ("Ring Cycle", "Opera", "Four operas about a cursed ring"),
+            ("Genesis 1", "Bible", "The heavens and the earth"),
+        ] {
+            let mut doc = Document::new();
+            doc.set_name(name.to_string());
+            doc.set_subject(subject.to_string());
+            doc.set_content(content.to_string());
+            library.mut_documents().push(doc);
+        }
+        create_inverted_index(&library)
+    }
+
+    /// Every result of `query` is explained with exactly its score.
+    fn assert_explains_scores(index: &InvertedIndex, query: &Query, ranking: &Ranking) {
+        let results = search(index, query, ranking, Page::default());
+        assert!(!results.docs.is_empty(), "{}", query);
+        for doc in &results.docs {
+            let explanation = explain(index, query, ranking, doc.doc_id);
+            assert!(
+                (explanation.value - doc.score).abs() <= 1e-5 * doc.score,
+                "{} in {}: {} != {}\n{}",
+                query,
+                doc.doc_id,
+                explanation.value,
+                doc.score,
+                explanation
+            );
+        }
+    }
+
+    #[test]
+    fn explanations_add_up_to_the_search_score() {
+        let index = index();
+        let mut ranking = Ranking::default();
+        for text in [
+            "ring",
+            "name:ring",
+            "content:ring gold",
+            "\"one ring\" power",
+        ] {
+            assert_explains_scores(&index, &parse_query(text), &ranking);
+        }
+        ranking.boost_subject("Bible", 3.0).unwrap();
+        assert_explains_scores(&index, &parse_query("ring subject:bible"), &ranking);
+    }
+
+    #[test]
+    fn synonym_matches_are_explained_with_their_weight() {
+        let index = index();
+        let mut thesaurus = Thesaurus::new();
+        add_entry(&mut thesaurus, "ring, band", false).unwrap();
+        let ranking = Ranking::default();
+        let (query, _) =
+            search_with_synonyms(&index, "ring", &[], &thesaurus, &ranking, Page::default());
+        assert_explains_scores(&index, &query, &ranking);
+
+        let explanation = explain(&index, &query, &ranking, 1);
+        assert!(explanation.to_string().contains("synonym content:band"));
+        assert!(!explanation.to_string().contains("synonym content:ring"));
+    }
+
+    #[test]
+    fn unmatched_clauses_are_left_out() {
+        let index = index();
+        let query = parse_query("ring heavens");
+        let explanation = explain(&index, &query, &Ranking::default(), 3);
+        assert_eq!(explanation.details.len(), 1);
+        assert_eq!(explanation.details[0].description, "clause heavens");
+    }
+
+    #[test]
+    fn analysis_ends_with_the_tokens_that_are_searched() {
+        for text in ["The Lord of the Rings", "ring,band;gold", "Is...", ""] {
+            let steps = analysis_steps(text);
+            let tokens: Vec<String> = tokenize(text).into_iter().map(|(_, t)| t).collect();
+            assert_eq!(steps.last().unwrap().1, tokens, "{}", text);
+        }
+        assert_eq!(
+            analysis_steps("Rings, of")[1].1,
+            ["Rings", "of"],
+            "punctuation is dropped"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::query::{MatchKind, Query, QueryNode};
+use crate::results::Match;
+use crate::utils::token_spans;
+
+/// Finds the words and phrases of a query in text. Built once per query, it
+/// splits text into the same tokens as the index, so a match is exactly what
+/// the index matched: whole tokens, and phrases only where all their terms
+/// appear at the right word distance.
+#[derive(Debug, Clone, Default)]
+pub struct Highlighter {
+    terms: HashMap<String, MatchKind>,
+    phrases: Vec<(Vec<(usize, String)>, MatchKind)>,
+}
+
+impl Highlighter {
+    pub fn new(query: &Query) -> Highlighter {
+        let mut highlighter = Highlighter::default();
+        for clause in &query.clauses {
+            highlighter.add(&clause.node, MatchKind::Direct);
+        }
+        highlighter
+    }
+
+    /// Alternatives of an OR-group after the first are synonym expansions.
+    /// A term or phrase reached both ways counts as direct.
+    fn add(&mut self, node: &QueryNode, kind: MatchKind) {
+        match node {
+            QueryNode::Term(term) => {
+                let existing = self.terms.entry(term.clone()).or_insert(kind);
+                if kind == MatchKind::Direct {
+                    *existing = kind;
+                }
+            }
+            QueryNode::Phrase(terms) => {
+                match self.phrases.iter_mut().find(|(phrase, _)| phrase == terms) {
+                    Some(existing) if kind == MatchKind::Direct => existing.1 = kind,
+                    Some(_) => {}
+                    None => self.phrases.push((terms.clone(), kind)),
+                }
+            }
+            QueryNode::Or(nodes) => {
+                for (i, node) in nodes.iter().enumerate() {
+                    self.add(node, if i == 0 { kind } else { MatchKind::Synonym });
+                }
+            }
+            QueryNode::Boost(node, _) => self.add(node, kind),
+        }
+    }
+
+    /// The matches in `text` in one pass over its tokens, sorted, with
+    /// overlapping matches merged. A phrase match spans from its first to its
+    /// last term; a merged match is direct if any part is.
+    pub fn matches(&self, text: &str) -> Vec<Match> {
+        let tokens: Vec<(usize, Range<usize>, String)> = token_spans(text)
+            .into_iter()
+            .map(|(pos, span)| {
+                let token = text[span.clone()].to_lowercase();
+                (pos, span, token)
+            })
+            .collect();
+
+        let mut found: Vec<(Range<usize>, MatchKind)> = Vec::new();
+        for (i, (pos, span, token)) in tokens.iter().enumerate() {
+            if let Some(&kind) = self.terms.get(token) {
+                found.push((span.clone(), kind));
+            }
+            for (phrase, kind) in &self.phrases {
+                if phrase[0].1 != *token {
+                    continue;
+                }
+                // Later terms must follow at the same word distance as in the phrase
+                let ends: Option<Vec<usize>> = phrase[1..]
+                    .iter()
+                    .map(|(offset, term)| {
+                        let target = pos + offset - phrase[0].0;
+                        tokens[i + 1..]
+                            .iter()
+                            .take_while(|(p, _, _)| *p <= target)
+                            .find(|(p, _, t)| *p == target && t == term)
+                            .map(|(_, span, _)| span.end)
+                    })
+                    .collect();
+                if let Some(end) = ends.and_then(|ends| ends.into_iter().max()) {
+                    found.push((span.start..end, *kind));
+                }
+            }
+        }
+        found.sort_unstable_by_key(|(range, _)| (range.start, range.end));
+
+        let mut merged: Vec<Match> = Vec::new();
+        for (range, kind) in found {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => {
+                    last.end = last.end.max(range.end);
+                    if kind == MatchKind::Direct {
+                        last.kind = kind;
+                    }
+                }
+                _ => merged.push(Match {
+                    start: range.start,
+                    end: range.end,
+                    kind,
+                }),
+            }
+        }
+        merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query;
+    use crate::thesaurus::{add_entry, expand_synonyms, Thesaurus};
+
+    /// The matched text and kind of every match in `text`.
+    fn matched<'a>(highlighter: &Highlighter, text: &'a str) -> Vec<(&'a str, MatchKind)> {
+        highlighter
+            .matches(text)
+            .into_iter()
+            .map(|m| (&text[m.start..m.end], m.kind))
+            .collect()
+    }
+
+    fn direct(text: &str) -> (&str, MatchKind) {
+        (text, MatchKind::Direct)
+    }
+
+    #[test]
+    fn terms_match_whole_tokens_only() {
+        let highlighter = Highlighter::new(&parse_query("ring"));
+        assert_eq!(
+            matched(&highlighter, "Rings, ringing, a RING. ring-bearer (ring)"),
+            [direct("RING")]
+        );
+        assert_eq!(matched(&highlighter, "one ring,two"), [direct("ring")]);
+    }
+
+    #[test]
+    fn phrases_match_only_where_they_occur_in_full() {
+        let highlighter = Highlighter::new(&parse_query("\"one ring\""));
+        assert!(matched(&highlighter, "one of the rings, a ring, one more").is_empty());
+        assert!(
+            matched(&highlighter, "ring one").is_empty(),
+            "the order matters"
+        );
+        assert_eq!(
+            matched(&highlighter, "The One Ring, one ring."),
+            [direct("One Ring"), direct("one ring")]
+        );
+    }
+
+    #[test]
+    fn phrases_are_one_span_across_stop_words() {
+        let highlighter = Highlighter::new(&parse_query("\"lord of the rings\""));
+        assert_eq!(
+            matched(&highlighter, "The Lord of the Rings."),
+            [direct("Lord of the Rings")]
+        );
+        assert!(
+            matched(&highlighter, "Lord of Rings").is_empty(),
+            "stop words keep their distance"
+        );
+    }
+
+    #[test]
+    fn overlapping_terms_and_phrases_merge() {
+        let highlighter = Highlighter::new(&parse_query("\"one ring\" ring rule"));
+        assert_eq!(
+            matched(&highlighter, "One ring to rule them, a ring"),
+            [direct("One ring"), direct("rule"), direct("ring")]
+        );
+    }
+
+    #[test]
+    fn synonyms_are_marked_unless_also_typed() {
+        let mut thesaurus = Thesaurus::new();
+        add_entry(&mut thesaurus, "ring, band, circle of gold", false).unwrap();
+        let highlighter = Highlighter::new(&expand_synonyms(&parse_query("ring"), &thesaurus));
+        assert_eq!(
+            matched(&highlighter, "A ring, a band, a circle of gold"),
+            [
+                direct("ring"),
+                ("band", MatchKind::Synonym),
+                ("circle of gold", MatchKind::Synonym)
+            ]
+        );
+
+        let both = Highlighter::new(&expand_synonyms(&parse_query("ring band"), &thesaurus));
+        assert_eq!(
+            matched(&both, "a band"),
+            [direct("band")],
+            "typed words are direct even if also a synonym"
+        );
+    }
+}
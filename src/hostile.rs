@@ -0,0 +1,158 @@
+//! Documents that try to take over the terminal they are printed to, run
+//! through every way librarian shows a document.
+
+use ratatui::backend::TestBackend;
+use ratatui::widgets::Block;
+use ratatui::Terminal;
+
+use librarian::highlighter::Highlighter;
+use librarian::query::parse_query;
+use librarian::render::{self, Highlight};
+use librarian::results::SearchResults;
+use librarian::search::Page;
+use librarian::{Index, Library, Query};
+
+use crate::display::full_document;
+use crate::pager::DocumentView;
+
+const SUBJECT: &str = "Hostile \x1b[5mblink";
+
+/// All of them mention "ring".
+const DOCUMENTS: &[(&str, &str)] = &[
+    (
+        "Title \x1b]0;pwned\x07",
+        "Printed raw, this ring sets the window title \x1b]0;pwned\x07 of the terminal.",
+    ),
+    (
+        "Clear screen",
+        "Wipes the screen \x1b[2J\x1b[H before the ring text and hides the cursor \x1b[?25l.",
+    ),
+    (
+        "Forged\n2. Document: Trusted (score 9.999)",
+        "A newline in the name forges an extra result line around the ring.",
+    ),
+    (
+        "Link",
+        "An \x1b]8;;http://example.com\x1b\\innocent ring \x1b]8;;\x1b\\ hyperlink to elsewhere.",
+    ),
+    (
+        "Bidi \u{202e}txt.exe",
+        "A right-to-left override \u{202e} reverses the ring text that follows it.",
+    ),
+    (
+        "Isolates \u{2067}",
+        "Isolates \u{2066}and marks\u{200f} reorder the ring \u{2069} text too.",
+    ),
+    (
+        "C1 controls",
+        "An eight bit CSI \u{9b}31m turns the ring red in some terminals.",
+    ),
+    (
+        "Backspaces",
+        "Overwrites what was shown: safe\x08\x08\x08\x08evil ring\rbell \x07.",
+    ),
+    (
+        "Windows",
+        "Lines of a ring\r\nending in carriage returns\r\n",
+    ),
+];
+
+/// The escape sequences of the default `Highlight`, which are meant to reach
+/// the terminal.
+const OWN_SEQUENCES: &[&str] = &["\x1b[31m", "\x1b[33m", "\x1b[0m"];
+
+fn library() -> Library {
+    let mut library = Library::new();
+    for (name, content) in DOCUMENTS {
+        library.add(name, SUBJECT, content);
+    }
+    library
+}
+
+fn search(library: &Library) -> SearchResults {
+    let query = Query::new("ring").page(Page {
+        offset: 0,
+        limit: DOCUMENTS.len(),
+    });
+    let results = Index::new(library).search(library, &query);
+    assert_eq!(results.hits.len(), DOCUMENTS.len());
+    results
+}
+
+/// Fails if `output` has a control character other than a newline or tab, a
+/// bidirectional control or an escaped carriage return left from a Windows
+/// line ending.
+fn assert_safe(output: &str, context: &str) {
+    let mut output = output.to_string();
+    for sequence in OWN_SEQUENCES {
+        output = output.replace(sequence, "");
+    }
+    let bad = output.chars().find(|&c| {
+        (c.is_control() && c != '\n' && c != '\t')
+            || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+    });
+    assert_eq!(bad, None, "{} in {:?}", context, output);
+    assert!(
+        !output.contains("\\u{d}\n"),
+        "{}: carriage return shown in {:?}",
+        context,
+        output
+    );
+}
+
+#[test]
+fn search_results_are_safe() {
+    let library = library();
+    let results = search(&library);
+    let highlight = Highlight::default();
+    let terminal = render::terminal(&results, Some(&highlight));
+    assert_safe(&terminal, "terminal");
+    assert!(terminal.contains("\x1b[31mring\x1b[0m"));
+
+    let plain = render::plain(&results);
+    assert_safe(&plain, "plain");
+    assert!(!plain
+        .lines()
+        .any(|line| line.starts_with("2. Document: Trusted")));
+    assert_safe(&render::html(&results), "html");
+}
+
+#[test]
+fn full_documents_are_safe() {
+    let library = library();
+    let highlighter = Highlighter::new(&parse_query("ring"));
+    for (id, (name, _)) in DOCUMENTS.iter().enumerate() {
+        let highlight = Highlight::default();
+        let document = full_document(&library, id, &highlighter, Some(&highlight));
+        assert_safe(&document, name);
+    }
+}
+
+#[test]
+fn document_views_are_safe() {
+    let library = library();
+    let highlighter = Highlighter::new(&parse_query("ring"));
+    for (id, (name, _)) in DOCUMENTS.iter().enumerate() {
+        let mut view = DocumentView::new(&library, id, &highlighter, Some(Highlight::default()));
+        let hits = highlighter.matches(&library.documents()[id].content).len();
+        assert!(hits > 0, "{}", name);
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        terminal
+            .draw(|frame| view.draw(frame, frame.area(), Block::bordered()))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert_safe(&screen, name);
+        assert!(
+            view.position().ends_with(&format!(" of {}", hits)),
+            "{}: {}",
+            name,
+            view.position()
+        );
+    }
+}
@@ -0,0 +1,349 @@
+//! Full-text search over a library of documents, each with a name, a subject
+//! and content.
+//!
+//! A [`Library`] holds the documents and an [`Index`] the inverted index built
+//! from one, along with the thesaurus and ranking it searches with. A [`Query`]
+//! is the query text with the page, filters and snippets wanted;
+//! [`Index::search`] runs it and returns [`SearchResults`], which can be
+//! serialized or laid out by the functions in [`render`]. The public modules
+//! below are the parts these are built from, for callers needing finer
+//! control.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+pub(crate) mod collector;
+pub mod explain;
+pub mod highlighter;
+pub mod library;
+pub(crate) mod maxscore;
+#[allow(unused_parens, mismatched_lifetime_syntaxes)]
+pub(crate) mod proto;
+pub mod query;
+pub mod render;
+pub mod results;
+pub mod scoring;
+pub mod search;
+pub(crate) mod sentences;
+pub mod thesaurus;
+pub(crate) mod utils;
+
+pub use proto::Document;
+pub use results::{SearchResults, SnippetOptions};
+pub use scoring::Ranking;
+pub use search::{Field, Page};
+pub use thesaurus::Thesaurus;
+
+use explain::Explanation;
+use query::parse_query;
+use search::{create_inverted_index, search_faceted, InvertedIndex};
+use thesaurus::expand_synonyms;
+
+/// A collection of documents. A document's id is its position, starting at 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Library {
+    inner: proto::Library,
+}
+
+impl Library {
+    pub fn new() -> Library {
+        Library::default()
+    }
+
+    /// Reads a library saved by [`Library::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Library> {
+        library::load_library(path.as_ref()).map(Library::from)
+    }
+
+    /// Writes the library as a protocol buffer.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        library::save_library(path.as_ref(), &self.inner)
+    }
+
+    /// Adds a document and returns its id.
+    pub fn add(&mut self, name: &str, subject: &str, content: &str) -> usize {
+        let mut doc = Document::new();
+        doc.set_name(name.to_string());
+        doc.set_subject(subject.to_string());
+        doc.set_content(content.to_string());
+        self.inner.mut_documents().push(doc);
+        self.inner.documents.len() - 1
+    }
+
+    /// Removes a document, leaving an empty one in its place so the ids of
+    /// the others stay the same. Returns the removed document.
+    pub fn remove(&mut self, id: usize) -> Option<Document> {
+        let doc = self.inner.mut_documents().get_mut(id)?;
+        Some(std::mem::take(doc))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Document> {
+        self.inner.documents.get(id)
+    }
+
+    pub fn documents(&self) -> &[Document] {
+        &self.inner.documents
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.documents.is_empty()
+    }
+
+    /// The protocol buffer message, as taken by the functions of the modules.
+    pub(crate) fn as_proto(&self) -> &proto::Library {
+        &self.inner
+    }
+}
+
+impl From<proto::Library> for Library {
+    fn from(inner: proto::Library) -> Library {
+        Library { inner }
+    }
+}
+
+/// An inverted index of a library, with the thesaurus used to expand queries
+/// and the ranking used to score results. It does not keep the library, so
+/// rebuild it whenever the library changes.
+pub struct Index {
+    inverted: InvertedIndex,
+    /// Shared with rebuilt indexes.
+    thesaurus: Arc<Thesaurus>,
+    ranking: Arc<Ranking>,
+}
+
+impl Index {
+    /// Indexes every document of `library`, with no synonyms and the default
+    /// ranking.
+    pub fn new(library: &Library) -> Index {
+        Index {
+            inverted: create_inverted_index(library.as_proto()),
+            thesaurus: Arc::new(Thesaurus::new()),
+            ranking: Arc::new(Ranking::default()),
+        }
+    }
+
+    /// Expands queries with synonyms from `thesaurus`, e.g. as loaded by
+    /// [`thesaurus::load_thesaurus`].
+    pub fn with_thesaurus(mut self, thesaurus: Thesaurus) -> Index {
+        self.thesaurus = Arc::new(thesaurus);
+        self
+    }
+
+    pub fn with_ranking(mut self, ranking: Ranking) -> Index {
+        self.ranking = Arc::new(ranking);
+        self
+    }
+
+    /// Indexes `library` again after it changed, with the same thesaurus and
+    /// ranking. This index is left as it is and can still be searched while
+    /// the new one is built.
+    pub fn rebuild(&self, library: &Library) -> Index {
+        Index {
+            inverted: create_inverted_index(library.as_proto()),
+            thesaurus: Arc::clone(&self.thesaurus),
+            ranking: Arc::clone(&self.ranking),
+        }
+    }
+
+    /// Runs `query` against `library`, which must be the library the index was
+    /// built from. This is [`search::search_with_synonyms`] with snippets,
+    /// counting facets only when the query asks for them.
+    pub fn search(&self, library: &Library, query: &Query) -> SearchResults {
+        let mut analyzed = self.analyze(&query.text);
+        analyzed.filters.extend_from_slice(&query.filters);
+        let top = if query.facets {
+            search_faceted(&self.inverted, &analyzed, &self.ranking, query.page)
+        } else {
+            search::search(&self.inverted, &analyzed, &self.ranking, query.page)
+        };
+        SearchResults::new(
+            library.as_proto(),
+            &query.text,
+            &analyzed,
+            &top,
+            query.page,
+            query.snippets,
+        )
+    }
+
+    /// The query [`Index::search`] runs for `text`, parsed and expanded with
+    /// synonyms, e.g. to highlight its matches with a
+    /// [`highlighter::Highlighter`].
+    pub fn analyze(&self, text: &str) -> query::Query {
+        expand_synonyms(&parse_query(text), &self.thesaurus)
+    }
+
+    /// Why document `doc_id` matched `query`, as given by [`Index::analyze`],
+    /// and how its score was computed.
+    pub fn explain(&self, query: &query::Query, doc_id: usize) -> Explanation {
+        explain::explain(&self.inverted, query, &self.ranking, doc_id)
+    }
+
+    pub fn inverted(&self) -> &InvertedIndex {
+        &self.inverted
+    }
+
+    pub fn thesaurus(&self) -> &Thesaurus {
+        &self.thesaurus
+    }
+
+    pub fn ranking(&self) -> &Ranking {
+        &self.ranking
+    }
+}
+
+/// What to search for: query text such as `subject:Bible ring` or
+/// `name:"Chapter 12"` (see [`query::parse_query`]), which page of results to
+/// return, facet values to narrow them to and how to cut snippets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    text: String,
+    page: Page,
+    filters: Vec<(Field, String)>,
+    snippets: SnippetOptions,
+    facets: bool,
+}
+
+impl Query {
+    /// The first page of results for `text`, with default snippets and no
+    /// facets.
+    pub fn new(text: impl Into<String>) -> Query {
+        Query {
+            text: text.into(),
+            page: Page::default(),
+            filters: Vec::new(),
+            snippets: SnippetOptions::default(),
+            facets: false,
+        }
+    }
+
+    pub fn page(mut self, page: Page) -> Query {
+        self.page = page;
+        self
+    }
+
+    /// Keeps only documents whose `field` is `value`, e.g. a subject. The
+    /// field should be one of [`Field::FACETS`].
+    pub fn filter(mut self, field: Field, value: impl Into<String>) -> Query {
+        self.filters.push((field, value.into()));
+        self
+    }
+
+    pub fn snippets(mut self, snippets: SnippetOptions) -> Query {
+        self.snippets = snippets;
+        self
+    }
+
+    /// Also counts every match by the fields in [`Field::FACETS`], as
+    /// [`search::search_faceted`] does. This looks up every matching document,
+    /// so it makes `total_hits` exact but costs more than the page alone.
+    pub fn facets(mut self, facets: bool) -> Query {
+        self.facets = facets;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use thesaurus::add_entry;
+
+    fn library() -> Library {
+        let mut library = Library::new();
+        library.add("Chapter 2", "Lord of the Rings", "The One Ring");
+        library.add("Numbers 6", "Bible", "A gold band");
+        library.add("Genesis 1", "Bible", "The heavens and the earth");
+        library
+    }
+
+    #[test]
+    fn documents_are_numbered_as_they_are_added() {
+        let mut library = library();
+        assert_eq!(library.len(), 3);
+        assert_eq!(library.add("Job 42", "Bible", "A ring of gold"), 3);
+        assert_eq!(library.get(3).unwrap().name, "Job 42");
+        assert!(library.get(4).is_none());
+        assert!(!library.is_empty() && Library::new().is_empty());
+    }
+
+    #[test]
+    fn search_expands_synonyms_and_applies_filters() {
+        let library = library();
+        let mut thesaurus = Thesaurus::new();
+        add_entry(&mut thesaurus, "ring, band", false).unwrap();
+        let index = Index::new(&library).with_thesaurus(thesaurus);
+
+        let results = index.search(&library, &Query::new("ring"));
+        assert_eq!(results.total_hits, 2);
+        let analyzed = index.analyze("ring");
+        assert!(analyzed.to_string().contains("band"), "{}", analyzed);
+        let lord = Query::new("ring").filter(Field::Subject, "Lord of the Rings");
+        let ids: Vec<usize> = index
+            .search(&library, &lord)
+            .hits
+            .iter()
+            .map(|hit| hit.id)
+            .collect();
+        assert_eq!(ids, [0]);
+        let results = index.search(&library, &Query::new("ring"));
+        assert!(results.facets.is_empty());
+        let results = index.search(&library, &Query::new("ring").facets(true));
+        assert_eq!(results.facets[0].field, Field::Subject);
+        assert_eq!(results.facets[0].counts.len(), 2);
+
+        let second = Query::new("ring").page(Page {
+            offset: 1,
+            limit: 1,
+        });
+        let results = index.search(&library, &second);
+        assert_eq!((results.offset, results.hits.len()), (1, 1));
+        assert_eq!(results.hits[0].rank, 2);
+    }
+
+    #[test]
+    fn results_are_explained_with_their_score() {
+        let library = library();
+        let index = Index::new(&library);
+        let results = index.search(&library, &Query::new("ring gold"));
+        assert!(!results.hits.is_empty());
+        let analyzed = index.analyze("ring gold");
+        for hit in &results.hits {
+            let explanation = index.explain(&analyzed, hit.id);
+            assert_eq!(explanation.value, hit.score, "{}", explanation);
+        }
+    }
+
+    #[test]
+    fn removed_documents_do_not_change_scores() {
+        let texts = [
+            "The One Ring",
+            "A gold ring",
+            "Rings of power",
+            "No match here",
+        ];
+        let mut library = Library::new();
+        let mut kept = Library::new();
+        for (id, text) in texts.iter().enumerate() {
+            library.add("Chapter", "Tales", text);
+            if id != 2 {
+                kept.add("Chapter", "Tales", text);
+            }
+        }
+        library.remove(2).unwrap();
+
+        let scores = |library: &Library| -> Vec<f32> {
+            let results = Index::new(library).search(library, &Query::new("ring"));
+            results.hits.iter().map(|hit| hit.score).collect()
+        };
+        assert_eq!(scores(&library), scores(&kept));
+    }
+}
@@ -1,20 +1,21 @@
 use protobuf::Message;
-use std::io::Read;
-use std::io::Write;
+use std::io::{self, Read, Write};
+use std::path::Path;
 
 use crate::proto::Library;
 
-const LIBRARY_BIN: &str = "library.bin";
+/// Where the command line tool keeps its library.
+pub const LIBRARY_BIN: &str = "library.bin";
 
-pub fn load_library() -> Library {
-    let mut file = std::fs::File::open(LIBRARY_BIN).unwrap();
+pub(crate) fn load_library(path: &Path) -> io::Result<Library> {
+    let mut file = std::fs::File::open(path)?;
     let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer).unwrap();
-    Library::parse_from_bytes(&buffer).unwrap()
+    file.read_to_end(&mut buffer)?;
+    Library::parse_from_bytes(&buffer).map_err(io::Error::other)
 }
 
-pub fn save_library(library: &Library) {
-    let mut file = std::fs::File::create(LIBRARY_BIN).unwrap();
-    let bytes = library.write_to_bytes().unwrap();
-    file.write_all(&bytes).unwrap();
+pub(crate) fn save_library(path: &Path, library: &Library) -> io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    let bytes = library.write_to_bytes().map_err(io::Error::other)?;
+    file.write_all(&bytes)
 }
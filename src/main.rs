@@ -1,46 +1,106 @@
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process;
 
-mod library;
-mod proto;
-mod search;
-mod utils;
+mod batch;
+mod bench;
+mod cli;
+mod display;
+#[cfg(test)]
+mod hostile;
+mod pager;
+mod repl;
+mod server;
+#[cfg(test)]
+mod testing;
+mod tui;
 
-use library::{load_library, save_library};
-use proto::{Document, Library};
-use search::{create_inverted_index, load_thesaurus, search_with_synonyms};
-use utils::{display_full_document, display_results};
+use librarian::library::LIBRARY_BIN;
+use librarian::render;
+use librarian::thesaurus::{load_thesaurus, load_thesaurus_from};
+use librarian::{Index, Library, Query};
 
-fn main() -> io::Result<()> {
-    let mut library = generate_demo_data();
-    save_library(&library);
-    library = load_library();
-
-    let thesaurus = load_thesaurus()?;
-    let index = create_inverted_index(&library);
+use cli::OutputFormat;
+use repl::Session;
+use tui::App;
 
-    print!("Enter your search query: ");
-    io::stdout().flush().unwrap();
-    let mut query = String::new();
-    io::stdin().read_line(&mut query).unwrap();
-    let query = query.trim();
+fn main() -> io::Result<()> {
+    let options = cli::parse_args(std::env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("Error: {}\n\n{}", err, cli::USAGE);
+        process::exit(2);
+    });
+    if options.help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+    if options.command == cli::Command::Bench {
+        bench::run(options.docs.unwrap_or(20000), options.page());
+        return Ok(());
+    }
 
-    let binding = vec![];
-    let results = search_with_synonyms(&index, query, &thesaurus);
-    let synonyms = thesaurus.get(query).unwrap_or(&binding);
-    let snippet_map = display_results(&library, results, query, synonyms);
+    let mut library = generate_demo_data();
+    library.save(LIBRARY_BIN)?;
+    library = Library::load(LIBRARY_BIN)?;
 
-    print!("Enter the number of the document you want to view in full: ");
-    io::stdout().flush().unwrap();
-    let mut selection = String::new();
-    io::stdin().read_line(&mut selection).unwrap();
-    let selection: usize = selection.trim().parse().expect("Invalid input");
+    let thesaurus = match &options.thesaurus {
+        Some(path) => load_thesaurus_from(path, options.thesaurus_format(), false),
+        None => load_thesaurus(),
+    }
+    .unwrap_or_else(|err| {
+        eprintln!("Error: {}", err);
+        process::exit(1);
+    });
+    let index = Index::new(&library)
+        .with_thesaurus(thesaurus)
+        .with_ranking(options.ranking());
 
-    if let Some((doc_id, _)) = snippet_map.get(&selection) {
-        display_full_document(&library, *doc_id, query, &synonyms);
-    } else {
-        println!("Invalid selection.");
+    if options.command == cli::Command::Batch {
+        let stdout = io::stdout().lock();
+        let errors = match &options.input {
+            Some(path) => {
+                let file = File::open(path).unwrap_or_else(|err| {
+                    eprintln!("Error: cannot read {}: {}", path.display(), err);
+                    process::exit(1);
+                });
+                let file = BufReader::new(file);
+                batch::run(&library, &index, &options, file, stdout)?
+            }
+            None => batch::run(&library, &index, &options, io::stdin().lock(), stdout)?,
+        };
+        if errors > 0 {
+            eprintln!("{} queries could not be run", errors);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+    if options.command == cli::Command::Serve {
+        return server::run(library, index, options.port.unwrap_or(8080), &options);
+    }
+    if options.command == cli::Command::Tui {
+        return App::new(&library, &index, &options).run();
+    }
+    if options.format == OutputFormat::Text {
+        return Session::new(&library, &index, &options)
+            .run()
+            .map_err(io::Error::other);
     }
 
+    // Other formats answer a single query from stdin, for scripts
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    let query = Query::new(input)
+        .page(options.page())
+        .snippets(options.snippet_options())
+        .facets(true);
+    let results = index.search(&library, &query);
+    let output = match options.format {
+        OutputFormat::Text | OutputFormat::Plain => render::plain(&results),
+        OutputFormat::Json => render::json(&results)?,
+        OutputFormat::JsonLines => render::json_lines(&results)?,
+        OutputFormat::Html => render::html(&results),
+    };
+    print!("{}", output);
     Ok(())
 }
 
@@ -77,10 +137,6 @@ fn generate_demo_data() -> Library {
 
 fn add_documents(library: &mut Library, subject: &str, chapters: Vec<(&str, &str)>) {
     for (name, content) in chapters {
-        let mut doc = Document::new();
-        doc.set_name(name.to_string());
-        doc.set_subject(subject.to_string());
-        doc.set_content(content.to_string());
-        library.mut_documents().push(doc);
+        library.add(name, subject, content);
     }
 }
@@ -0,0 +1,317 @@
+use crate::collector::TopK;
+use crate::query::{Clause, Query, QueryNode};
+use crate::scoring::{FieldStats, Ranking};
+use crate::search::{
+    clause_fields, passes_filters, phrase_occurrences, Collected, Field, InvertedIndex,
+};
+
+/// The documents a term or phrase occurs in, sorted by doc_id.
+enum Postings<'a> {
+    /// Borrowed straight from the index; the term frequency is the number of positions.
+    Term(&'a [(usize, Vec<usize>)]),
+    /// Phrases are matched up front into (doc_id, frequency) pairs.
+    Phrase(Vec<(usize, usize)>),
+}
+
+impl Postings<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Postings::Term(postings) => postings.len(),
+            Postings::Phrase(postings) => postings.len(),
+        }
+    }
+
+    fn doc(&self, i: usize) -> usize {
+        match self {
+            Postings::Term(postings) => postings[i].0,
+            Postings::Phrase(postings) => postings[i].0,
+        }
+    }
+
+    fn tf(&self, i: usize) -> usize {
+        match self {
+            Postings::Term(postings) => postings[i].1.len(),
+            Postings::Phrase(postings) => postings[i].1,
+        }
+    }
+}
+
+/// Walks the postings of one leaf of the query (a term or phrase in one field)
+/// in doc_id order, scoring the current document on demand.
+struct Cursor<'a> {
+    postings: Postings<'a>,
+    i: usize,
+    field: Field,
+    /// Product of the synonym and field boosts above the leaf.
+    weight: f32,
+    stats: FieldStats,
+    /// No document can get more than this from the leaf.
+    upper_bound: f32,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(
+        index: &'a InvertedIndex,
+        field: Field,
+        leaf: &QueryNode,
+        weight: f32,
+        ranking: &Ranking,
+    ) -> Self {
+        let stats = index.field_stats(field);
+        let (postings, max_tf, min_length) = match leaf {
+            QueryNode::Term(term) => match index.posting_list(field, term) {
+                Some(list) => (
+                    Postings::Term(list.postings.as_slice()),
+                    list.max_tf,
+                    list.min_length,
+                ),
+                None => (Postings::Term(&[]), 0, 0),
+            },
+            QueryNode::Phrase(terms) => {
+                let postings: Vec<(usize, usize)> = phrase_occurrences(index, field, terms)
+                    .map(|(doc_id, positions)| (doc_id, positions.len()))
+                    .collect();
+                let max_tf = postings.iter().map(|(_, tf)| *tf).max().unwrap_or(0);
+                let min_length = postings
+                    .iter()
+                    .map(|(doc_id, _)| index.field_length(field, *doc_id))
+                    .min()
+                    .unwrap_or(0);
+                (Postings::Phrase(postings), max_tf, min_length)
+            }
+            _ => unreachable!("cursors are only built for terms and phrases"),
+        };
+        let upper_bound = match postings.len() {
+            0 => 0.0,
+            doc_freq => {
+                weight
+                    * ranking
+                        .scorer
+                        .max_score(max_tf, min_length, doc_freq, &stats)
+            }
+        };
+
+        Cursor {
+            postings,
+            i: 0,
+            field,
+            weight,
+            stats,
+            upper_bound,
+        }
+    }
+
+    fn doc(&self) -> Option<usize> {
+        (self.i < self.postings.len()).then(|| self.postings.doc(self.i))
+    }
+
+    fn next(&mut self) {
+        self.i += 1;
+    }
+
+    /// Moves to the first document at or after `target`, galloping ahead and
+    /// then binary searching so long skips stay cheap.
+    fn advance_to(&mut self, target: usize) {
+        let len = self.postings.len();
+        if self.i >= len || self.postings.doc(self.i) >= target {
+            return;
+        }
+        let mut step = 1;
+        let mut low = self.i;
+        while low + step < len && self.postings.doc(low + step) < target {
+            low += step;
+            step *= 2;
+        }
+        let mut high = (low + step).min(len);
+        low += 1;
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.postings.doc(mid) < target {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        self.i = low;
+    }
+
+    /// Score of the current document.
+    fn score(&self, index: &InvertedIndex, ranking: &Ranking) -> f32 {
+        let doc_id = self.postings.doc(self.i);
+        let tf = self.postings.tf(self.i);
+        let doc_length = index.field_length(self.field, doc_id);
+        self.weight
+            * ranking
+                .scorer
+                .score(tf, doc_length, self.postings.len(), &self.stats)
+    }
+}
+
+/// Flattens a query node into its term and phrase leaves with their weights.
+/// Scores add up over OR-groups, so a document's score is the weighted sum of
+/// the leaves it matches.
+fn leaves<'q>(node: &'q QueryNode, weight: f32, out: &mut Vec<(&'q QueryNode, f32)>) {
+    match node {
+        QueryNode::Term(_) | QueryNode::Phrase(_) => out.push((node, weight)),
+        QueryNode::Or(nodes) => {
+            for node in nodes {
+                leaves(node, weight, out);
+            }
+        }
+        QueryNode::Boost(node, boost) => leaves(node, weight * boost, out),
+    }
+}
+
+fn clause_cursors<'a>(
+    index: &'a InvertedIndex,
+    clause: &Clause,
+    ranking: &Ranking,
+) -> Vec<Cursor<'a>> {
+    let mut cursors = Vec::new();
+    for field in clause_fields(clause) {
+        let mut field_leaves = Vec::new();
+        leaves(&clause.node, ranking.field_boost(field), &mut field_leaves);
+        for (leaf, weight) in field_leaves {
+            let cursor = Cursor::new(index, field, leaf, weight, ranking);
+            if cursor.doc().is_some() {
+                cursors.push(cursor);
+            }
+        }
+    }
+    cursors
+}
+
+/// Collects the `k` best documents for `query` with MaxScore pruning.
+///
+/// The leaves of the OR'ed clauses are sorted by their score upper bound. Once
+/// the top-k is full, the longest prefix of leaves whose bounds together cannot
+/// beat the k-th score becomes "non-essential": documents are only taken from
+/// the remaining essential leaves, and the non-essential ones are merely probed
+/// for those candidates, and only while the candidate can still make it.
+/// Field-scoped clauses are required and checked for every candidate.
+pub fn collect_top(index: &InvertedIndex, query: &Query, ranking: &Ranking, k: usize) -> Collected {
+    let (scoped, unscoped): (Vec<&Clause>, Vec<&Clause>) = query
+        .clauses
+        .iter()
+        .partition(|clause| clause.field.is_some());
+    let mut required: Vec<Vec<Cursor>> = scoped
+        .iter()
+        .map(|clause| clause_cursors(index, clause, ranking))
+        .collect();
+
+    // Without unscoped clauses, the first field-scoped clause drives the search:
+    // like the OR'ed clauses, a result has to match at least one of its leaves.
+    let mut optional: Vec<Cursor> = if unscoped.is_empty() && !required.is_empty() {
+        required.remove(0)
+    } else {
+        unscoped
+            .iter()
+            .flat_map(|clause| clause_cursors(index, clause, ranking))
+            .collect()
+    };
+    optional.sort_by(|a, b| a.upper_bound.total_cmp(&b.upper_bound));
+
+    let required_bound: f32 = required.iter().flatten().map(|c| c.upper_bound).sum();
+    let max_subject_boost = ranking.subject_boosts.values().copied().fold(1.0, f32::max);
+    // prefix_bounds[i]: the most leaves 0..=i can add to a score
+    let prefix_bounds: Vec<f32> = optional
+        .iter()
+        .scan(0.0, |sum, cursor| {
+            *sum += cursor.upper_bound;
+            Some(*sum)
+        })
+        .collect();
+
+    let mut top = TopK::new(k);
+    let mut total_hits = 0;
+    let mut exact = true;
+
+    loop {
+        let threshold = top.threshold();
+        let non_essential = threshold.map_or(0, |threshold| {
+            prefix_bounds
+                .iter()
+                .take_while(|&&bound| (bound + required_bound) * max_subject_boost <= threshold)
+                .count()
+        });
+        if non_essential > 0 {
+            exact = false;
+        }
+
+        let Some(doc_id) = optional[non_essential..]
+            .iter()
+            .filter_map(|c| c.doc())
+            .min()
+        else {
+            break;
+        };
+
+        let score = if passes_filters(index, query, doc_id) {
+            score_required(&mut required, doc_id, index, ranking)
+        } else {
+            None
+        };
+        if let Some(mut score) = score {
+            total_hits += 1;
+            for cursor in &optional[non_essential..] {
+                if cursor.doc() == Some(doc_id) {
+                    score += cursor.score(index, ranking);
+                }
+            }
+
+            let subject_boost = ranking.subject_boost(index.field_value(Field::Subject, doc_id));
+            let mut competitive = true;
+            for i in (0..non_essential).rev() {
+                if threshold.is_some_and(|t| (score + prefix_bounds[i]) * subject_boost <= t) {
+                    competitive = false;
+                    break;
+                }
+                let cursor = &mut optional[i];
+                cursor.advance_to(doc_id);
+                if cursor.doc() == Some(doc_id) {
+                    score += cursor.score(index, ranking);
+                }
+            }
+            if competitive {
+                top.push(doc_id, score * subject_boost);
+            }
+        }
+
+        for cursor in &mut optional[non_essential..] {
+            if cursor.doc() == Some(doc_id) {
+                cursor.next();
+            }
+        }
+    }
+
+    Collected {
+        top,
+        total_hits,
+        exact,
+    }
+}
+
+/// Moves the required clauses' cursors to `doc_id` and sums their scores, or
+/// returns `None` if some required clause does not match the document.
+fn score_required(
+    required: &mut [Vec<Cursor>],
+    doc_id: usize,
+    index: &InvertedIndex,
+    ranking: &Ranking,
+) -> Option<f32> {
+    let mut score = 0.0;
+    for clause in required {
+        let mut matched = false;
+        for cursor in clause.iter_mut() {
+            cursor.advance_to(doc_id);
+            if cursor.doc() == Some(doc_id) {
+                score += cursor.score(index, ranking);
+                matched = true;
+            }
+        }
+        if !matched {
+            return None;
+        }
+    }
+    Some(score)
+}
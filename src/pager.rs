@@ -0,0 +1,390 @@
+use std::io;
+use std::ops::Range;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use librarian::highlighter::Highlighter;
+use librarian::query::MatchKind;
+use librarian::render::{self, sanitize_line, sanitize_text, wrap, Highlight};
+use librarian::results::Match;
+use librarian::Library;
+
+const KEYS: &str = "n/p: next/previous hit  g: first hit  Space/b: page  j/k: line  q: quit";
+
+/// A document shown in full, wrapped to the width of its pane, with the hit
+/// the reader is at.
+pub struct DocumentView {
+    title: String,
+    /// The content as stored, which `matches` and `lines` point into. It is
+    /// sanitized a span at a time as it is drawn.
+    text: String,
+    matches: Vec<Match>,
+    lines: Vec<Range<usize>>,
+    width: usize,
+    height: usize,
+    hit: usize,
+    scroll: usize,
+    /// Whether to scroll to `hit` the next time the view is drawn.
+    follow: bool,
+    highlight: Option<Highlight>,
+}
+
+impl DocumentView {
+    /// A view of the content of `doc_id` at its first hit, with matches
+    /// styled unless `highlight` is `None`. The current hit is always shown
+    /// reversed.
+    pub fn new(
+        library: &Library,
+        doc_id: usize,
+        highlighter: &Highlighter,
+        highlight: Option<Highlight>,
+    ) -> DocumentView {
+        let doc = &library.documents()[doc_id];
+        let text = doc.content.clone();
+        DocumentView {
+            title: format!(
+                "{} ({})",
+                sanitize_line(&doc.name),
+                sanitize_line(&doc.subject)
+            ),
+            matches: highlighter.matches(&text),
+            text,
+            lines: Vec::new(),
+            width: 0,
+            height: 0,
+            hit: 0,
+            scroll: 0,
+            follow: true,
+            highlight,
+        }
+    }
+
+    /// Starts at the first hit at or after word `position` of the content,
+    /// such as the start of a snippet.
+    pub fn at_word(mut self, position: usize) -> DocumentView {
+        let start = self
+            .text
+            .split_whitespace()
+            .nth(position)
+            .map_or(self.text.len(), |word| {
+                word.as_ptr() as usize - self.text.as_ptr() as usize
+            });
+        let hit = self.matches.partition_point(|m| m.end <= start);
+        self.hit = hit.min(self.matches.len().saturating_sub(1));
+        self
+    }
+
+    /// Moves to the next or previous hit, wrapping around.
+    pub fn cycle(&mut self, forward: bool) {
+        let n = self.matches.len();
+        if n > 0 {
+            self.hit = if forward {
+                (self.hit + 1) % n
+            } else {
+                (self.hit + n - 1) % n
+            };
+            self.follow = true;
+        }
+    }
+
+    pub fn scroll_by(&mut self, lines: isize) {
+        self.scroll = self.scroll.saturating_add_signed(lines);
+    }
+
+    /// Handles the keys shared by every document view, returning false for
+    /// other keys.
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        let page = self.height.saturating_sub(1).max(1) as isize;
+        match code {
+            KeyCode::Char('n') => self.cycle(true),
+            KeyCode::Char('p') | KeyCode::Char('N') => self.cycle(false),
+            KeyCode::Char('g') | KeyCode::Home => {
+                self.hit = 0;
+                self.follow = true;
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.scroll_by(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.scroll_by(1),
+            KeyCode::PageUp | KeyCode::Char('b') => self.scroll_by(-page),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll_by(page),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Draws the visible lines inside `block`, titled with the document and
+    /// the position in it.
+    pub fn draw(&mut self, frame: &mut Frame, area: Rect, block: Block) {
+        let inner = block.inner(area);
+        self.layout(inner.width as usize, inner.height as usize);
+        let lines: Vec<Line> = self.lines[self.scroll..]
+            .iter()
+            .take(self.height)
+            .map(|line| self.styled_line(line.clone()))
+            .collect();
+        let title = format!("{} - {}", self.title, self.position());
+        frame.render_widget(Paragraph::new(lines).block(block.title(title)), area);
+    }
+
+    /// Rewraps for a pane of `width` by `height` and brings the current hit
+    /// into view if it moved.
+    fn layout(&mut self, width: usize, height: usize) {
+        if width != self.width {
+            self.lines = wrap(&self.text, width);
+            self.width = width;
+        }
+        self.height = height;
+        if self.follow {
+            if let Some(hit) = self.matches.get(self.hit) {
+                let line = self.lines.partition_point(|line| line.end < hit.start);
+                self.scroll = line.saturating_sub(height / 3);
+            }
+            self.follow = false;
+        }
+        self.scroll = self.scroll.min(self.lines.len().saturating_sub(1));
+    }
+
+    /// The shown lines and the current hit, e.g. `lines 1-20 of 45, hit 2 of 5`.
+    pub fn position(&self) -> String {
+        let last = (self.scroll + self.height).min(self.lines.len());
+        let hits = match self.matches.len() {
+            0 => "no hits".to_string(),
+            n => format!("hit {} of {}", self.hit + 1, n),
+        };
+        format!(
+            "lines {}-{} of {}, {}",
+            self.scroll + 1,
+            last,
+            self.lines.len(),
+            hits
+        )
+    }
+
+    /// One wrapped line with the matches on it styled and the current hit
+    /// reversed.
+    fn styled_line(&self, line: Range<usize>) -> Line<'static> {
+        let text = &self.text;
+        let mut spans = Vec::new();
+        let mut last = line.start;
+        for (i, m) in self.matches.iter().enumerate() {
+            let (start, end) = (m.start.max(line.start), m.end.min(line.end));
+            if start >= end {
+                continue;
+            }
+            if start > last {
+                spans.push(Span::raw(sanitize_text(&text[last..start]).into_owned()));
+            }
+            let mut style = match_style(self.highlight.as_ref(), m.kind);
+            if i == self.hit {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            spans.push(Span::styled(
+                sanitize_text(&text[start..end]).into_owned(),
+                style,
+            ));
+            last = end;
+        }
+        if last < line.end {
+            spans.push(Span::raw(sanitize_text(&text[last..line.end]).into_owned()));
+        }
+        Line::from(spans)
+    }
+}
+
+/// The style of a match of `kind`, as on the command line; unstyled if
+/// `highlight` is `None`.
+pub fn match_style(highlight: Option<&Highlight>, kind: MatchKind) -> Style {
+    highlight.map_or(Style::new(), |highlight| {
+        terminal_style(highlight.style(kind))
+    })
+}
+
+/// `style` for ratatui. SGR codes it cannot show, such as blinking, are
+/// ignored.
+fn terminal_style(style: &render::Style) -> Style {
+    let mut codes = style.codes().filter_map(|code| code.parse::<u8>().ok());
+    let mut result = Style::new();
+    while let Some(code) = codes.next() {
+        result = match code {
+            0 => Style::new(),
+            1 => result.add_modifier(Modifier::BOLD),
+            2 => result.add_modifier(Modifier::DIM),
+            3 => result.add_modifier(Modifier::ITALIC),
+            4 => result.add_modifier(Modifier::UNDERLINED),
+            7 => result.add_modifier(Modifier::REVERSED),
+            9 => result.add_modifier(Modifier::CROSSED_OUT),
+            30..=37 => result.fg(Color::Indexed(code - 30)),
+            90..=97 => result.fg(Color::Indexed(code - 90 + 8)),
+            40..=47 => result.bg(Color::Indexed(code - 40)),
+            100..=107 => result.bg(Color::Indexed(code - 100 + 8)),
+            38 | 48 => {
+                let color = match codes.next() {
+                    Some(5) => codes.next().map(Color::Indexed),
+                    Some(2) => match (codes.next(), codes.next(), codes.next()) {
+                        (Some(r), Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                match (code, color) {
+                    (38, Some(color)) => result.fg(color),
+                    (_, Some(color)) => result.bg(color),
+                    (_, None) => result,
+                }
+            }
+            _ => result,
+        };
+    }
+    result
+}
+
+/// Shows `document` full screen until the reader quits.
+pub fn page(mut document: DocumentView) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = page_loop(&mut terminal, &mut document);
+    ratatui::restore();
+    result
+}
+
+fn page_loop(terminal: &mut DefaultTerminal, document: &mut DocumentView) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| {
+            let [text_area, keys_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+            document.draw(frame, text_area, Block::bordered());
+            frame.render_widget(Paragraph::new(KEYS), keys_area);
+        })?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            code => {
+                document.handle_key(code);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librarian::query::parse_query;
+
+    /// A view of one document about rings, laid out in a pane of
+    /// `width` by `height`.
+    fn view(content: &str, query: &str, width: usize, height: usize) -> DocumentView {
+        let mut library = Library::new();
+        library.add("Chapter 2", "Lord of the Rings", content);
+        let highlighter = Highlighter::new(&parse_query(query));
+        let mut view = DocumentView::new(&library, 0, &highlighter, None);
+        view.layout(width, height);
+        view
+    }
+
+    #[test]
+    fn hits_cycle_and_wrap_around() {
+        let mut rings = view("ring one ring two ring", "ring", 40, 5);
+        assert_eq!(rings.position(), "lines 1-1 of 1, hit 1 of 3");
+        rings.cycle(true);
+        rings.cycle(true);
+        assert_eq!(rings.hit, 2);
+        rings.cycle(true);
+        assert_eq!(rings.hit, 0, "wraps to the first hit");
+        rings.cycle(false);
+        assert_eq!(rings.hit, 2, "wraps to the last hit");
+
+        let mut none = view("nothing to see", "ring", 40, 5);
+        none.cycle(true);
+        assert_eq!(none.position(), "lines 1-1 of 1, no hits");
+    }
+
+    #[test]
+    fn at_word_starts_at_the_next_hit() {
+        let content = "ring a b c ring d e f ring";
+        assert_eq!(view(content, "ring", 40, 5).at_word(0).hit, 0);
+        assert_eq!(view(content, "ring", 40, 5).at_word(1).hit, 1);
+        assert_eq!(view(content, "ring", 40, 5).at_word(4).hit, 1);
+        assert_eq!(view(content, "ring", 40, 5).at_word(5).hit, 2);
+        assert_eq!(
+            view(content, "ring", 40, 5).at_word(99).hit,
+            2,
+            "past the end stays at the last hit"
+        );
+    }
+
+    #[test]
+    fn keys_move_between_hits_and_scroll() {
+        let content = (0..30)
+            .map(|i| if i == 25 { "ring" } else { "word" })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut view = view(&content, "ring", 10, 5);
+        assert_eq!(
+            view.scroll,
+            25 - 5 / 3,
+            "the first hit is brought into view"
+        );
+        assert!(view.handle_key(KeyCode::Char('k')));
+        view.layout(10, 5);
+        assert_eq!(view.scroll, 23);
+        assert!(view.handle_key(KeyCode::PageUp));
+        view.layout(10, 5);
+        assert_eq!(view.scroll, 19);
+        assert!(view.handle_key(KeyCode::Char('g')));
+        view.layout(10, 5);
+        assert_eq!(view.scroll, 24, "g returns to the first hit");
+        assert!(view.handle_key(KeyCode::PageDown));
+        view.layout(10, 5);
+        assert_eq!(view.scroll, 28);
+        assert!(view.handle_key(KeyCode::Char(' ')));
+        view.layout(10, 5);
+        assert_eq!(view.scroll, 29, "stops at the last line");
+        assert!(!view.handle_key(KeyCode::Char('x')));
+    }
+
+    fn style(spec: &str) -> Style {
+        terminal_style(&render::Style::parse(spec).unwrap())
+    }
+
+    #[test]
+    fn styles_follow_the_command_line() {
+        assert_eq!(style("red"), Style::new().fg(Color::Indexed(1)));
+        assert_eq!(
+            style("bold+yellow"),
+            Style::new()
+                .add_modifier(Modifier::BOLD)
+                .fg(Color::Indexed(3))
+        );
+        assert_eq!(
+            style("4;38;5;208;48;2;1;2;3"),
+            Style::new()
+                .add_modifier(Modifier::UNDERLINED)
+                .fg(Color::Indexed(208))
+                .bg(Color::Rgb(1, 2, 3))
+        );
+        assert_eq!(style("5;96"), Style::new().fg(Color::Indexed(14)));
+        assert_eq!(style("38;5"), Style::new());
+    }
+
+    #[test]
+    fn matches_are_plain_without_highlight() {
+        assert_eq!(match_style(None, MatchKind::Direct), Style::new());
+        let highlight = Highlight {
+            direct: render::Style::parse("green").unwrap(),
+            synonym: render::Style::parse("blue").unwrap(),
+        };
+        assert_eq!(
+            match_style(Some(&highlight), MatchKind::Synonym),
+            Style::new().fg(Color::Indexed(4))
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::search::Field;
+use crate::utils::tokenize;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryNode {
+    Term(String),
+    /// Phrase terms with their word positions relative to the quoted text, so
+    /// removed stop words still leave a gap (`"lord of the rings"`).
+    Phrase(Vec<(usize, String)>),
+    /// Matches if any of the alternatives match; produced by synonym expansion.
+    Or(Vec<QueryNode>),
+    /// Scales the weight of every match of the inner node.
+    Boost(Box<QueryNode>, f32),
+}
+
+/// Whether a term was part of the query as typed or only added as a synonym.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    Direct,
+    Synonym,
+}
+
+/// Renders analyzed nodes in query syntax, e.g. `(ring OR band^0.5)`.
+impl fmt::Display for QueryNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryNode::Term(term) => write!(f, "{}", term),
+            QueryNode::Phrase(terms) => {
+                let words: Vec<&str> = terms.iter().map(|(_, term)| term.as_str()).collect();
+                write!(f, "\"{}\"", words.join(" "))
+            }
+            QueryNode::Or(nodes) => {
+                let nodes: Vec<String> = nodes.iter().map(|node| node.to_string()).collect();
+                write!(f, "({})", nodes.join(" OR "))
+            }
+            QueryNode::Boost(node, weight) => write!(f, "{}^{}", node, weight),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clause {
+    pub field: Option<Field>,
+    pub node: QueryNode,
+    /// Word position of the clause within the query string.
+    pub pos: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Query {
+    pub clauses: Vec<Clause>,
+    /// Exact metadata values results must have, such as a subject selected to
+    /// drill down into. Unlike field-scoped clauses they do not affect scores.
+    pub filters: Vec<(Field, String)>,
+}
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.field {
+            Some(field) => write!(f, "{}:{}", field.name(), self.node),
+            None => write!(f, "{}", self.node),
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts: Vec<String> = self.clauses.iter().map(|c| c.to_string()).collect();
+        for (field, value) in &self.filters {
+            parts.push(format!("[{}={}]", field.name(), value));
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+/// Parses a query string such as `subject:Bible ring` or `name:"Chapter 12"`.
+///
+/// A `field:` prefix scopes the following word or quoted phrase to that field.
+/// Quoted text becomes a phrase, everything else is a bare term searched in all
+/// fields. Unknown prefixes are treated as ordinary text.
+pub fn parse_query(input: &str) -> Query {
+    let clauses = split_clauses(input)
+        .into_iter()
+        .filter_map(|(field, text, pos)| analyze_node(text).map(|node| Clause { field, node, pos }))
+        .collect();
+
+    Query {
+        clauses,
+        filters: Vec::new(),
+    }
+}
+
+/// Splits a query string into the raw text of its clauses, before analysis,
+/// with their field and word position.
+pub fn split_clauses(input: &str) -> Vec<(Option<Field>, &str, usize)> {
+    let mut clauses = Vec::new();
+    let mut rest = input.trim_start();
+
+    while !rest.is_empty() {
+        let (field, value) = split_field(rest);
+        let (text, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+
+        let pos = input[..input.len() - rest.len()].split_whitespace().count();
+        clauses.push((field, text, pos));
+        rest = remaining.trim_start();
+    }
+
+    clauses
+}
+
+fn split_field(text: &str) -> (Option<Field>, &str) {
+    if let Some((prefix, value)) = text.split_once(':') {
+        if let Some(field) = Field::from_name(prefix) {
+            return (Some(field), value);
+        }
+    }
+    (None, text)
+}
+
+/// Analyzes free text into a single term, or a phrase if it has several tokens.
+pub fn analyze_node(text: &str) -> Option<QueryNode> {
+    let mut tokens = tokenize(text);
+    match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(|(_, term)| QueryNode::Term(term)),
+        _ => Some(QueryNode::Phrase(tokens)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(field: Option<Field>, term: &str, pos: usize) -> Clause {
+        Clause {
+            field,
+            node: QueryNode::Term(term.to_string()),
+            pos,
+        }
+    }
+
+    fn phrase(terms: &[(usize, &str)]) -> QueryNode {
+        QueryNode::Phrase(terms.iter().map(|&(pos, t)| (pos, t.to_string())).collect())
+    }
+
+    #[test]
+    fn split_clauses_keeps_fields_quotes_and_positions() {
+        assert_eq!(
+            split_clauses(r#"  ring name:"Chapter 12" subject:Bible"#),
+            vec![
+                (None, "ring", 0),
+                (Some(Field::Name), "Chapter 12", 1),
+                (Some(Field::Subject), "Bible", 3),
+            ]
+        );
+        assert_eq!(
+            split_clauses(r#"gold "one ring"#),
+            vec![(None, "gold", 0), (None, "one ring", 1)]
+        );
+    }
+
+    #[test]
+    fn field_prefixes_scope_the_next_word() {
+        let query = parse_query("SUBJECT:Bible ring");
+        assert_eq!(
+            query.clauses,
+            vec![
+                term(Some(Field::Subject), "bible", 0),
+                term(None, "ring", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_prefixes_are_ordinary_text() {
+        let query = parse_query("author:Tolkien");
+        assert_eq!(query.clauses.len(), 1);
+        assert_eq!(query.clauses[0].field, None);
+        assert_eq!(
+            query.clauses[0].node,
+            phrase(&[(0, "author"), (0, "tolkien")])
+        );
+    }
+
+    #[test]
+    fn quoted_text_becomes_a_phrase() {
+        let query = parse_query(r#"name:"Chapter 12""#);
+        assert_eq!(query.clauses.len(), 1);
+        assert_eq!(query.clauses[0].field, Some(Field::Name));
+        assert_eq!(query.clauses[0].node, phrase(&[(0, "chapter"), (1, "12")]));
+    }
+
+    #[test]
+    fn an_unclosed_quote_runs_to_the_end() {
+        let query = parse_query(r#"gold "one ring"#);
+        assert_eq!(query.clauses[0], term(None, "gold", 0));
+        assert_eq!(query.clauses[1].node, phrase(&[(0, "one"), (1, "ring")]));
+        assert_eq!(query.clauses[1].pos, 1);
+    }
+
+    #[test]
+    fn stop_words_leave_gaps_in_phrases() {
+        let query = parse_query(r#""Lord of the Rings""#);
+        assert_eq!(query.clauses[0].node, phrase(&[(0, "lord"), (3, "rings")]));
+    }
+
+    #[test]
+    fn clauses_of_only_stop_words_are_dropped() {
+        let query = parse_query(r#"the ring "of the""#);
+        assert_eq!(query.clauses, vec![term(None, "ring", 1)]);
+    }
+
+    #[test]
+    fn a_quoted_single_word_is_a_term() {
+        let query = parse_query(r#""Ring.""#);
+        assert_eq!(query.clauses, vec![term(None, "ring", 0)]);
+    }
+}
@@ -0,0 +1,739 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::query::MatchKind;
+use crate::results::{segments, Match, SearchHit, SearchResults, Snippet};
+use crate::search::Field;
+
+/// Style names accepted by `Style::parse`, with their SGR parameters.
+const STYLE_NAMES: &[(&str, &str)] = &[
+    ("bold", "1"),
+    ("dim", "2"),
+    ("italic", "3"),
+    ("underline", "4"),
+    ("reverse", "7"),
+    ("black", "30"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+    ("white", "37"),
+];
+
+/// An ANSI text style, kept as SGR parameters such as `1;33`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style(String);
+
+impl Style {
+    /// Parses style names or SGR numbers separated by spaces, commas or `+`,
+    /// e.g. `bold+yellow` or `1;4;36`.
+    pub fn parse(spec: &str) -> Result<Style, String> {
+        let mut codes = Vec::new();
+        for part in spec.split(|c: char| c.is_whitespace() || c == ',' || c == '+' || c == ';') {
+            if part.is_empty() {
+                continue;
+            }
+            if part.chars().all(|c| c.is_ascii_digit()) {
+                codes.push(part.to_string());
+                continue;
+            }
+            let code = STYLE_NAMES
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(part))
+                .ok_or_else(|| format!("unknown style `{}`", part))?;
+            codes.push(code.1.to_string());
+        }
+        if codes.is_empty() {
+            return Err(format!("empty style `{}`", spec));
+        }
+        Ok(Style(codes.join(";")))
+    }
+
+    /// The SGR parameters of the style, in order.
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.0.split(';')
+    }
+
+    pub fn paint(&self, text: &str) -> String {
+        format!("\x1b[{}m{}\x1b[0m", self.0, text)
+    }
+}
+
+/// The styles of direct and synonym matches on a terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub direct: Style,
+    pub synonym: Style,
+}
+
+impl Default for Highlight {
+    /// Red for words of the query, yellow for their synonyms.
+    fn default() -> Self {
+        Highlight {
+            direct: Style("31".to_string()),
+            synonym: Style("33".to_string()),
+        }
+    }
+}
+
+impl Highlight {
+    /// The style of matches of `kind`.
+    pub fn style(&self, kind: MatchKind) -> &Style {
+        match kind {
+            MatchKind::Direct => &self.direct,
+            MatchKind::Synonym => &self.synonym,
+        }
+    }
+
+    fn paint(&self, text: &str, kind: MatchKind) -> String {
+        self.style(kind).paint(text)
+    }
+}
+
+/// `text` with every control character escaped (`\u{1b}`), so documents
+/// cannot move the cursor, retitle the window or forge output lines when
+/// printed. Also escapes bidirectional overrides, which reorder the text after
+/// them. Newlines and tabs are kept if `keep_layout` is set. Windows line
+/// endings (`\r\n`) count as plain newlines.
+fn escape_controls(text: &str, keep_layout: bool) -> Cow<'_, str> {
+    let unsafe_char = |c: char| is_unsafe(c, keep_layout);
+    if !text.chars().any(unsafe_char) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\r' && chars.peek() == Some(&'\n') {
+            continue;
+        }
+        if unsafe_char(c) {
+            escaped.extend(c.escape_unicode());
+        } else {
+            escaped.push(c);
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Whether `escape_controls` escapes `c`.
+fn is_unsafe(c: char, keep_layout: bool) -> bool {
+    let layout = c == '\n' || c == '\t';
+    (c.is_control() && !(keep_layout && layout))
+        || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Makes a single line value, such as a name or subject, safe to print.
+pub fn sanitize_line(text: &str) -> Cow<'_, str> {
+    escape_controls(text, false)
+}
+
+/// Makes multi-line text, such as document content, safe to print.
+pub fn sanitize_text(text: &str) -> Cow<'_, str> {
+    escape_controls(text, true)
+}
+
+/// Text for a terminal, with matches coloured unless `highlight` is `None`.
+pub fn terminal(results: &SearchResults, highlight: Option<&Highlight>) -> String {
+    text(results, |matched, kind| match highlight {
+        Some(highlight) => highlight.paint(matched, kind),
+        None => matched.to_string(),
+    })
+}
+
+/// The same layout as `terminal`, without colours.
+pub fn plain(results: &SearchResults) -> String {
+    terminal(results, None)
+}
+
+/// Sanitized `text` with the given matches coloured unless `highlight` is `None`.
+pub fn highlight_text(text: &str, matches: &[Match], highlight: Option<&Highlight>) -> String {
+    segments(text, matches)
+        .into_iter()
+        .map(|(text, kind)| {
+            let text = sanitize_text(text);
+            match (kind, highlight) {
+                (Some(kind), Some(highlight)) => highlight.paint(&text, kind),
+                _ => text.into_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Splits `text` into lines at most `width` columns wide, breaking at spaces
+/// where possible and at every newline. Wide characters such as CJK take two
+/// columns and combining marks none. Control characters are measured as
+/// `sanitize_text` escapes them, so `text` can be wrapped before it is
+/// sanitized. Lines are byte ranges into `text`, without the line ending or
+/// the space they were broken at.
+pub fn wrap(text: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in text.split('\n') {
+        let paragraph = line.strip_suffix('\r').unwrap_or(line);
+        let mut start = offset;
+        let mut used = 0;
+        let mut last_space = None;
+        for (i, c) in paragraph.char_indices() {
+            let pos = offset + i;
+            let size = columns(c);
+            if used + size > width {
+                if c == ' ' {
+                    lines.push(start..pos);
+                    start = pos + 1;
+                    used = 0;
+                    last_space = None;
+                    continue;
+                }
+                if let Some(space) = last_space {
+                    lines.push(start..space);
+                    start = space + 1;
+                    used = text[start..pos].chars().map(columns).sum();
+                }
+                if used + size > width && pos > start {
+                    lines.push(start..pos);
+                    start = pos;
+                    used = 0;
+                }
+                last_space = None;
+            }
+            if c == ' ' {
+                last_space = Some(pos);
+            }
+            used += size;
+        }
+        lines.push(start..offset + paragraph.len());
+        offset += line.len() + 1;
+    }
+    lines
+}
+
+/// The columns `c` takes once sanitized.
+fn columns(c: char) -> usize {
+    if is_unsafe(c, true) {
+        c.escape_unicode().count()
+    } else {
+        c.width().unwrap_or(0)
+    }
+}
+
+pub fn json(results: &SearchResults) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(results).map(|json| json + "\n")
+}
+
+/// One JSON object per hit and line.
+pub fn json_lines(results: &SearchResults) -> serde_json::Result<String> {
+    let mut out = String::new();
+    for hit in &results.hits {
+        out.push_str(&serde_json::to_string(hit)?);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// An HTML fragment with matches wrapped in `<mark>`.
+pub fn html(results: &SearchResults) -> String {
+    let mut out = String::from("<div class=\"results\">\n");
+    out.push_str(&format!("<p class=\"summary\">{}</p>\n", summary(results)));
+    if !results.hits.is_empty() {
+        out.push_str(&format!("<ol start=\"{}\">\n", results.offset + 1));
+        for hit in &results.hits {
+            out.push_str(&format!(
+                "<li>\n<h3>{}</h3>\n<p class=\"subject\">{}</p>\n<p class=\"score\">{:.3}</p>\n<p class=\"hits\">{}</p>\n<ul class=\"snippets\">\n",
+                escape_html(&sanitize_line(&hit.name)),
+                escape_html(&sanitize_line(&hit.subject)),
+                hit.score,
+                hit.hit_count
+            ));
+            for snippet in &hit.snippets {
+                let text = marked(
+                    snippet,
+                    |matched, kind| {
+                        let class = match kind {
+                            MatchKind::Direct => "",
+                            MatchKind::Synonym => " class=\"synonym\"",
+                        };
+                        format!("<mark{}>{}</mark>", class, escape_html(matched))
+                    },
+                    escape_html,
+                );
+                out.push_str(&format!(
+                    "<li class=\"{}\">{}</li>\n",
+                    snippet.field.name(),
+                    text
+                ));
+            }
+            out.push_str("</ul>\n</li>\n");
+        }
+        out.push_str("</ol>\n");
+    }
+    for (field, counts) in facet_lines(results) {
+        out.push_str(&format!(
+            "<p class=\"facets\">By {}: {}</p>\n",
+            field,
+            escape_html(&counts)
+        ));
+    }
+    out.push_str("</div>\n");
+    out
+}
+
+/// The layout of `terminal` and `plain`, with matches passed through `mark`.
+fn text(results: &SearchResults, mark: impl Fn(&str, MatchKind) -> String) -> String {
+    let mut out = String::new();
+    out.push_str(&summary(results));
+    out.push('\n');
+
+    // Results are ranked, so repeat the heading whenever the subject changes
+    let mut current_subject = None;
+    for hit in &results.hits {
+        if current_subject != Some(&hit.subject) {
+            out.push_str(&format!("Subject: {}\n", sanitize_line(&hit.subject)));
+            current_subject = Some(&hit.subject);
+        }
+        out.push_str(&hit_line(hit));
+        for snippet in &hit.snippets {
+            let text = marked(snippet, &mark, str::to_string);
+            match snippet.field {
+                Field::Content => out.push_str(&format!("   - Snippet: {}\n", text)),
+                field => out.push_str(&format!("   - Snippet: [{}] {}\n", field.name(), text)),
+            }
+        }
+    }
+
+    if !results.hits.is_empty() {
+        for (field, counts) in facet_lines(results) {
+            out.push_str(&format!("By {}: {}\n", field, counts));
+        }
+    }
+    out
+}
+
+fn summary(results: &SearchResults) -> String {
+    if results.hits.is_empty() {
+        return format!("No results ({} matching documents).", results.total_hits);
+    }
+    format!(
+        "Showing results {}-{} of {}{}",
+        results.offset + 1,
+        results.offset + results.hits.len(),
+        if results.total_hits_exact {
+            ""
+        } else {
+            "at least "
+        },
+        results.total_hits
+    )
+}
+
+fn hit_line(hit: &SearchHit) -> String {
+    format!(
+        "{}. Document: {} (score {:.3}, {} {})\n",
+        hit.rank,
+        sanitize_line(&hit.name),
+        hit.score,
+        hit.hit_count,
+        if hit.hit_count == 1 { "hit" } else { "hits" }
+    )
+}
+
+/// Every facet as its field name and a `value (count), ...` list.
+fn facet_lines(results: &SearchResults) -> Vec<(&'static str, String)> {
+    results
+        .facets
+        .iter()
+        .map(|facet| {
+            let counts: Vec<String> = facet
+                .counts
+                .iter()
+                .map(|c| format!("{} ({})", sanitize_line(&c.value), c.count))
+                .collect();
+            (facet.field.name(), counts.join(", "))
+        })
+        .collect()
+}
+
+/// Sanitized snippet text with matched pieces passed through `mark` and the
+/// rest through `other`.
+fn marked(
+    snippet: &Snippet,
+    mark: impl Fn(&str, MatchKind) -> String,
+    other: impl Fn(&str) -> String,
+) -> String {
+    snippet
+        .segments()
+        .into_iter()
+        .map(|(text, kind)| {
+            let text = sanitize_line(text);
+            match kind {
+                Some(kind) => mark(&text, kind),
+                None => other(&text),
+            }
+        })
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::proto::{Document, Library};
+    use crate::results::SnippetOptions;
+    use crate::scoring::Ranking;
+    use crate::search::{create_inverted_index, search_with_synonyms, Page};
+    use crate::thesaurus::{add_entry, Thesaurus};
+
+    fn library() -> Library {
+        let mut library = Library::new();
+        for (name, subject, content) in [
+            (
+                "Chapter 2",
+                "Lord of the Rings",
+                "The One Ring. Ring-bearers rest",
+            ),
+            ("Numbers 6", "Bible", "A <gold> ring & a RING of gold"),
+            ("Genesis 1", "Bible", "The heavens and the earth"),
+        ] {
+            let mut doc = Document::new();
+            doc.set_name(name.to_string());
+            doc.set_subject(subject.to_string());
+            doc.set_content(content.to_string());
+            library.mut_documents().push(doc);
+        }
+        library
+    }
+
+    fn results(input: &str, page: Page) -> SearchResults {
+        let library = library();
+        let index = create_inverted_index(&library);
+        let (query, top) = search_with_synonyms(
+            &index,
+            input,
+            &[],
+            &Thesaurus::new(),
+            &Ranking::default(),
+            page,
+        );
+        SearchResults::new(
+            &library,
+            input,
+            &query,
+            &top,
+            page,
+            SnippetOptions::default(),
+        )
+    }
+
+    #[test]
+    fn json_carries_totals_ranks_and_facets() {
+        let page = Page {
+            offset: 1,
+            limit: 1,
+        };
+        let json: Value = serde_json::from_str(&json(&results("ring", page)).unwrap()).unwrap();
+        assert_eq!(json["query"], "ring");
+        assert_eq!(json["total_hits"], 2);
+        assert_eq!(json["total_hits_exact"], true);
+        assert_eq!(json["offset"], 1);
+        let hits = json["hits"].as_array().unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0]["rank"], 2);
+        let facet = &json["facets"][0];
+        assert_eq!(facet["field"], "subject");
+        assert_eq!(facet["counts"].as_array().unwrap().len(), 2);
+        assert_eq!(facet["counts"][0]["count"], 1);
+    }
+
+    #[test]
+    fn json_matches_are_byte_ranges_of_the_snippet_text() {
+        let json: Value =
+            serde_json::from_str(&json(&results("ring", Page::default())).unwrap()).unwrap();
+        let mut checked = 0;
+        for hit in json["hits"].as_array().unwrap() {
+            for snippet in hit["snippets"].as_array().unwrap() {
+                assert_eq!(snippet["field"], "content");
+                let text = snippet["text"].as_str().unwrap();
+                for m in snippet["matches"].as_array().unwrap() {
+                    let start = m["start"].as_u64().unwrap() as usize;
+                    let end = m["end"].as_u64().unwrap() as usize;
+                    assert!(text[start..end].eq_ignore_ascii_case("ring"), "{}", text);
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked >= 3);
+    }
+
+    #[test]
+    fn json_lines_hold_one_hit_each() {
+        let results = results("gold", Page::default());
+        let lines = json_lines(&results).unwrap();
+        assert_eq!(lines.lines().count(), 1);
+        let hit: Value = serde_json::from_str(lines.lines().next().unwrap()).unwrap();
+        assert_eq!(hit["name"], "Numbers 6");
+        assert_eq!(hit["subject"], "Bible");
+        assert_eq!(hit["rank"], 1);
+        assert!(json_lines(&self::results("dragon", Page::default()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn html_marks_matches_and_escapes_text() {
+        let html = html(&results("gold", Page::default()));
+        assert!(html.contains("<ol start=\"1\">"));
+        assert!(html.contains("<h3>Numbers 6</h3>"));
+        assert!(html.contains("A &lt;gold&gt; ring &amp; a RING of <mark>gold</mark>"));
+        assert!(html.contains("<p class=\"facets\">By subject: Bible (1)</p>"));
+        assert!(!html.contains("<gold>"));
+    }
+
+    #[test]
+    fn plain_text_repeats_the_subject_heading_when_it_changes() {
+        let text = plain(&results("ring", Page::default()));
+        assert!(text.starts_with("Showing results 1-2 of 2\n"));
+        assert_eq!(text.matches("Subject: ").count(), 2);
+        assert!(!text.contains('\x1b'));
+        let highlight = Highlight::default();
+        assert!(
+            terminal(&results("ring", Page::default()), Some(&highlight))
+                .contains("\x1b[31mring\x1b[0m")
+        );
+        assert_eq!(
+            plain(&results("dragon", Page::default())),
+            "No results (0 matching documents).\n"
+        );
+    }
+
+    #[test]
+    fn styles_are_names_or_sgr_codes() {
+        assert_eq!(
+            Style::parse("bold+yellow").unwrap().paint("x"),
+            "\x1b[1;33mx\x1b[0m"
+        );
+        assert_eq!(
+            Style::parse("Underline, RED").unwrap(),
+            Style::parse("4;31").unwrap()
+        );
+        assert_eq!(
+            Style::parse(" 38;5;208 ").unwrap().paint("x"),
+            "\x1b[38;5;208mx\x1b[0m"
+        );
+        assert_eq!(
+            Style::parse("sparkly"),
+            Err("unknown style `sparkly`".to_string())
+        );
+        assert!(Style::parse("bold+").is_ok());
+        assert!(Style::parse("").is_err());
+        assert!(Style::parse(" + ; ").is_err());
+        assert!(
+            Style::parse("\x1b[31m").is_err(),
+            "escape sequences are not codes"
+        );
+    }
+
+    fn synonym_results() -> SearchResults {
+        let library = library();
+        let index = create_inverted_index(&library);
+        let mut thesaurus = Thesaurus::new();
+        add_entry(&mut thesaurus, "gold, ring", false).unwrap();
+        let (query, top) = search_with_synonyms(
+            &index,
+            "gold",
+            &[],
+            &thesaurus,
+            &Ranking::default(),
+            Page::default(),
+        );
+        SearchResults::new(
+            &library,
+            "gold",
+            &query,
+            &top,
+            Page::default(),
+            SnippetOptions::default(),
+        )
+    }
+
+    #[test]
+    fn synonym_matches_get_their_own_style() {
+        let results = synonym_results();
+        let highlight = Highlight {
+            direct: Style::parse("red").unwrap(),
+            synonym: Style::parse("yellow").unwrap(),
+        };
+        let text = terminal(&results, Some(&highlight));
+        assert!(text.contains("\x1b[31mgold\x1b[0m"));
+        assert!(text.contains("\x1b[33mring\x1b[0m"));
+        assert!(!terminal(&results, None).contains('\x1b'));
+
+        let html = html(&results);
+        assert!(html.contains("<mark>gold</mark>"));
+        assert!(html.contains("<mark class=\"synonym\">ring</mark>"));
+        assert!(html.contains("<mark class=\"synonym\">Ring</mark>"));
+    }
+
+    #[test]
+    fn json_matches_have_a_kind() {
+        let json: Value = serde_json::from_str(&json(&synonym_results()).unwrap()).unwrap();
+        let mut kinds = Vec::new();
+        for hit in json["hits"].as_array().unwrap() {
+            for snippet in hit["snippets"].as_array().unwrap() {
+                let text = snippet["text"].as_str().unwrap();
+                for m in snippet["matches"].as_array().unwrap() {
+                    let start = m["start"].as_u64().unwrap() as usize;
+                    let end = m["end"].as_u64().unwrap() as usize;
+                    let kind = m["kind"].as_str().unwrap();
+                    let expected = if text[start..end].eq_ignore_ascii_case("gold") {
+                        "direct"
+                    } else {
+                        "synonym"
+                    };
+                    assert_eq!(kind, expected, "{}", &text[start..end]);
+                    kinds.push(kind.to_string());
+                }
+            }
+        }
+        assert!(kinds.iter().any(|kind| kind == "direct"));
+        assert!(kinds.iter().any(|kind| kind == "synonym"));
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(sanitize_line("a\x1b[2Jb"), "a\\u{1b}[2Jb");
+        assert_eq!(sanitize_line("csi \u{9b}31m"), "csi \\u{9b}31m");
+        assert_eq!(sanitize_line("bidi \u{202e}txt"), "bidi \\u{202e}txt");
+        assert_eq!(sanitize_line("one\ntwo"), "one\\u{a}two");
+        assert_eq!(sanitize_text("one\n\ttwo\x07"), "one\n\ttwo\\u{7}");
+        assert!(matches!(sanitize_text("plain text\n"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn documents_cannot_forge_output_lines_or_escapes() {
+        let mut doc = Document::new();
+        doc.set_name("Forged\n2. Document: Trusted".to_string());
+        doc.set_subject("Hostile \x1b[5mblink".to_string());
+        doc.set_content("Wipes the screen \x1b[2J before the ring".to_string());
+        let mut library = Library::new();
+        library.mut_documents().push(doc);
+        let index = create_inverted_index(&library);
+        let (query, top) = search_with_synonyms(
+            &index,
+            "ring",
+            &[],
+            &Thesaurus::new(),
+            &Ranking::default(),
+            Page::default(),
+        );
+        let results = SearchResults::new(
+            &library,
+            "ring",
+            &query,
+            &top,
+            Page::default(),
+            SnippetOptions::default(),
+        );
+
+        let text = terminal(&results, Some(&Highlight::default()));
+        assert_eq!(
+            text.matches('\x1b').count(),
+            2,
+            "only the highlight: {:?}",
+            text
+        );
+        assert!(text.contains("\\u{1b}[2J"));
+        assert!(!text.contains("\n2. Document"));
+        assert!(!plain(&results).contains('\x1b'));
+        assert!(!html(&results).contains('\x1b'));
+    }
+
+    /// The lines `wrap` splits `text` into.
+    fn wrapped(text: &str, width: usize) -> Vec<&str> {
+        wrap(text, width)
+            .into_iter()
+            .map(|line| &text[line])
+            .collect()
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces_and_newlines() {
+        assert_eq!(
+            wrapped("the one ring to rule them", 10),
+            ["the one", "ring to", "rule them"]
+        );
+        assert_eq!(wrapped("one\n\ntwo", 10), ["one", "", "two"]);
+        assert_eq!(wrapped("", 10), [""]);
+        assert_eq!(wrapped("exactly10!", 10), ["exactly10!"]);
+    }
+
+    #[test]
+    fn wrap_splits_words_longer_than_the_width() {
+        assert_eq!(wrapped("abcdefghij kl", 4), ["abcd", "efgh", "ij", "kl"]);
+        assert_eq!(wrapped("abc", 0), ["a", "b", "c"]);
+        assert_eq!(wrapped("a abcdefgh", 4), ["a", "abcd", "efgh"]);
+    }
+
+    #[test]
+    fn wrap_fills_lines_to_exactly_the_width() {
+        assert_eq!(wrapped("abcd efgh", 4), ["abcd", "efgh"]);
+        assert_eq!(wrapped("ab cd ef", 5), ["ab cd", "ef"]);
+    }
+
+    #[test]
+    fn wrap_keeps_blank_lines() {
+        assert_eq!(wrapped("\n", 10), ["", ""]);
+        assert_eq!(wrapped("one\n\n\ntwo\n", 10), ["one", "", "", "two", ""]);
+        assert_eq!(
+            wrapped("one two\n\nthree", 3),
+            ["one", "two", "", "thr", "ee"]
+        );
+    }
+
+    #[test]
+    fn wrap_counts_columns_of_wide_and_zero_width_characters() {
+        assert_eq!(wrapped("指輪物語 指輪", 4), ["指輪", "物語", "指輪"]);
+        assert_eq!(wrapped("a指輪", 4), ["a指", "輪"]);
+        assert_eq!(wrapped("指", 1), ["指"]);
+        let accented = "cafe\u{301} cafe\u{301}";
+        assert_eq!(wrapped(accented, 4), ["cafe\u{301}", "cafe\u{301}"]);
+        assert_eq!(
+            wrapped("a\u{200b}b\u{200b}c d", 3),
+            ["a\u{200b}b\u{200b}c", "d"]
+        );
+    }
+
+    #[test]
+    fn wrap_measures_controls_as_they_are_escaped() {
+        assert_eq!(wrapped("ab\x1bc d", 9), ["ab\x1bc", "d"]);
+        assert_eq!(wrapped("ab\x1bc d", 8), ["ab\x1b", "c d"]);
+        assert_eq!(wrapped("one\r\ntwo\r\n", 10), ["one", "two", ""]);
+        assert_eq!(wrapped("one\rtwo", 20), ["one\rtwo"]);
+    }
+
+    #[test]
+    fn windows_line_endings_are_newlines() {
+        assert_eq!(sanitize_text("one\r\ntwo\r\n"), "one\ntwo\n");
+        assert_eq!(sanitize_text("carriage\rreturn"), "carriage\\u{d}return");
+        assert_eq!(sanitize_line("one\r\ntwo"), "one\\u{a}two");
+    }
+}
@@ -0,0 +1,432 @@
+use std::io::{self, IsTerminal};
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::cli::Options;
+use crate::display::{display_explanations, display_query_analysis, full_document};
+use crate::pager::{self, DocumentView};
+use librarian::highlighter::Highlighter;
+use librarian::render::{self, sanitize_line, Highlight};
+use librarian::{Field, Index, Library, Page, Query, SearchResults, SnippetOptions};
+
+/// Where line history is kept between sessions.
+const HISTORY_FILE: &str = ".librarian_history";
+
+const HELP: &str = "\
+Type a query to search, or one of these commands:
+  :open <N> [S]     Read result N in full, from its first hit or the hit of
+                    its snippet S
+  :next             Show the next page of results
+  :back             Go back to the previous results
+  :subject <NAME>   Narrow the results to a subject
+  :help             Show this help
+  :quit             Leave (or press Ctrl-D)";
+
+/// A page of results and the query that produced it, so `:back` can return to
+/// it without searching again.
+struct View {
+    query: Query,
+    page: Page,
+    results: SearchResults,
+}
+
+/// An interactive session over a loaded library: the index is built once and
+/// kept warm across queries.
+pub struct Session<'a> {
+    library: &'a Library,
+    index: &'a Index,
+    page: Page,
+    snippets: SnippetOptions,
+    highlight: Option<Highlight>,
+    explain: bool,
+    current: Option<View>,
+    back: Vec<View>,
+}
+
+impl<'a> Session<'a> {
+    pub fn new(library: &'a Library, index: &'a Index, options: &Options) -> Session<'a> {
+        Session {
+            library,
+            index,
+            page: options.page(),
+            snippets: options.snippet_options(),
+            highlight: options.highlight(),
+            explain: options.explain,
+            current: None,
+            back: Vec::new(),
+        }
+    }
+
+    /// Reads and runs lines with editing and history until `:quit` or end of
+    /// input.
+    pub fn run(&mut self) -> rustyline::Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        // There is no history yet on the first run
+        let _ = editor.load_history(HISTORY_FILE);
+        println!("Type a query to search, or :help for commands.");
+        loop {
+            let line = match editor.readline("librarian> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => break,
+                Err(err) => return Err(err),
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            editor.add_history_entry(line)?;
+            if !self.execute(line) {
+                break;
+            }
+        }
+        // Losing the history is not worth failing over
+        let _ = editor.save_history(HISTORY_FILE);
+        Ok(())
+    }
+
+    /// Runs one line: a `:command` or else a query. Returns false to quit.
+    fn execute(&mut self, line: &str) -> bool {
+        let outcome = match parse_command(line) {
+            Ok(Command::Quit) => return false,
+            Ok(Command::Help) => {
+                println!("{}", HELP);
+                return true;
+            }
+            Ok(Command::Search(input)) => {
+                if self.explain {
+                    display_query_analysis(input, &self.index.analyze(input));
+                }
+                self.search(input);
+                Ok(())
+            }
+            Ok(Command::Open(rank, snippet)) => {
+                match self.open(rank, snippet) {
+                    Ok(target) => self.read(target),
+                    Err(message) => println!("{}", message),
+                }
+                return true;
+            }
+            Ok(Command::Next) => self.next(),
+            Ok(Command::Back) => self.back(),
+            Ok(Command::Narrow(field, value)) => self.narrow(field, value),
+            Err(message) => Err(message),
+        };
+        match outcome {
+            Ok(()) => self.print_current(),
+            Err(message) => println!("{}", message),
+        }
+        true
+    }
+
+    fn search(&mut self, input: &str) {
+        let query = Query::new(input)
+            .page(self.page)
+            .snippets(self.snippets)
+            .facets(true);
+        self.show(query, self.page);
+    }
+
+    /// The result with overall rank `rank` on the current page, to read from
+    /// its first hit or from the start of its snippet numbered `snippet`.
+    fn open(&self, rank: usize, snippet: Option<usize>) -> Result<OpenTarget, String> {
+        let view = self.current()?;
+        let hit = view.results.hit(rank).ok_or_else(|| {
+            format!(
+                "No result {} on this page; use :open with a number shown above.",
+                rank
+            )
+        })?;
+        let snippet = match snippet {
+            Some(n) if n >= 1 && n <= hit.snippets.len() => Some(&hit.snippets[n - 1]),
+            Some(_) => {
+                return Err(format!(
+                    "Result {} has {} snippets.",
+                    hit.rank,
+                    hit.snippets.len()
+                ))
+            }
+            None => None,
+        };
+        Ok(OpenTarget {
+            doc_id: hit.id,
+            highlighter: Highlighter::new(&self.index.analyze(view.query.text())),
+            word: snippet
+                .filter(|snippet| snippet.field == Field::Content)
+                .map(|snippet| snippet.position),
+        })
+    }
+
+    /// Opens a document in the pager, or prints it when not on a terminal.
+    fn read(&self, target: OpenTarget) {
+        if !io::stdout().is_terminal() {
+            let document = full_document(
+                self.library,
+                target.doc_id,
+                &target.highlighter,
+                self.highlight.as_ref(),
+            );
+            print!("{}", document);
+            return;
+        }
+        let mut document = DocumentView::new(
+            self.library,
+            target.doc_id,
+            &target.highlighter,
+            self.highlight.clone(),
+        );
+        if let Some(word) = target.word {
+            document = document.at_word(word);
+        }
+        if let Err(err) = pager::page(document) {
+            println!("Error: {}", err);
+        }
+    }
+
+    fn next(&mut self) -> Result<(), String> {
+        let view = self.current()?;
+        let page = Page {
+            offset: view.page.end(),
+            limit: view.page.limit,
+        };
+        if page.offset >= view.results.total_hits {
+            return Err("No more results.".to_string());
+        }
+        let query = view.query.clone().page(page);
+        self.show(query, page);
+        Ok(())
+    }
+
+    fn back(&mut self) -> Result<(), String> {
+        let view = self.back.pop().ok_or("Nothing to go back to.")?;
+        self.current = Some(view);
+        Ok(())
+    }
+
+    /// Narrows the current results to a facet value, starting from their
+    /// first page.
+    fn narrow(&mut self, field: Field, value: &str) -> Result<(), String> {
+        let view = self.current()?;
+        let found = view
+            .results
+            .facets
+            .iter()
+            .filter(|facet| facet.field == field)
+            .find_map(|facet| facet.find(value))
+            .ok_or_else(|| {
+                format!(
+                    "No {} `{}` in these results.",
+                    field.name(),
+                    sanitize_line(value)
+                )
+            })?;
+        let page = Page {
+            offset: 0,
+            limit: view.page.limit,
+        };
+        let query = view.query.clone().filter(field, found).page(page);
+        self.show(query, page);
+        Ok(())
+    }
+
+    fn current(&self) -> Result<&View, String> {
+        self.current
+            .as_ref()
+            .ok_or_else(|| "Search for something first.".to_string())
+    }
+
+    /// Makes new results current, keeping the previous ones for `:back`.
+    fn show(&mut self, query: Query, page: Page) {
+        let results = self.index.search(self.library, &query);
+        let view = View {
+            query,
+            page,
+            results,
+        };
+        if let Some(previous) = self.current.replace(view) {
+            self.back.push(previous);
+        }
+    }
+
+    fn print_current(&self) {
+        let Some(view) = &self.current else { return };
+        print!(
+            "{}",
+            render::terminal(&view.results, self.highlight.as_ref())
+        );
+        if self.explain {
+            let query = self.index.analyze(view.query.text());
+            display_explanations(self.index, &query, &view.results);
+        }
+    }
+}
+
+/// A document to read, and the word of its content to start from.
+struct OpenTarget {
+    doc_id: usize,
+    highlighter: Highlighter,
+    word: Option<usize>,
+}
+
+/// A line typed at the prompt.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command<'a> {
+    Search(&'a str),
+    /// A rank, and optionally the number of one of its snippets.
+    Open(usize, Option<usize>),
+    Next,
+    Back,
+    Narrow(Field, &'a str),
+    Help,
+    Quit,
+}
+
+/// Parses a `:command`, or else a query. Fails with the message to show.
+fn parse_command(line: &str) -> Result<Command<'_>, String> {
+    let Some(command) = line.strip_prefix(':') else {
+        return Ok(Command::Search(line));
+    };
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name, arg.trim()),
+        None => (command, ""),
+    };
+    match name {
+        "open" | "o" => {
+            let mut numbers = arg.split_whitespace().map(str::parse::<usize>);
+            match (numbers.next(), numbers.next(), numbers.next()) {
+                (None, _, _) => Err("Use :open with a number shown above.".to_string()),
+                (Some(Ok(rank)), None, None) => Ok(Command::Open(rank, None)),
+                (Some(Ok(rank)), Some(Ok(snippet)), None) => Ok(Command::Open(rank, Some(snippet))),
+                _ => Err(format!(
+                    "No result `{}` on this page; use :open with a number shown above.",
+                    sanitize_line(arg)
+                )),
+            }
+        }
+        "next" | "n" => Ok(Command::Next),
+        "back" | "b" => Ok(Command::Back),
+        "help" | "h" | "?" => Ok(Command::Help),
+        "quit" | "q" | "exit" => Ok(Command::Quit),
+        _ => match Field::from_name(name).filter(|field| Field::FACETS.contains(field)) {
+            Some(field) => Ok(Command::Narrow(field, arg)),
+            None => Err(format!(
+                "Unknown command `:{}`. Type :help for the list of commands.",
+                sanitize_line(name)
+            )),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::parse_args;
+    use crate::testing::library;
+
+    /// Runs `test` with a session showing two results per page.
+    fn with_session(test: impl FnOnce(&mut Session)) {
+        let library = library();
+        let index = Index::new(&library);
+        let options = parse_args(["--limit".to_string(), "2".to_string()]).unwrap();
+        test(&mut Session::new(&library, &index, &options));
+    }
+
+    #[test]
+    fn lines_are_queries_unless_they_start_with_a_colon() {
+        assert_eq!(parse_command("gold ring"), Ok(Command::Search("gold ring")));
+        assert_eq!(parse_command(":open 2"), Ok(Command::Open(2, None)));
+        assert_eq!(parse_command(":o  3 "), Ok(Command::Open(3, None)));
+        assert_eq!(parse_command(":open 3 2"), Ok(Command::Open(3, Some(2))));
+        assert_eq!(parse_command(":n"), Ok(Command::Next));
+        assert_eq!(parse_command(":back"), Ok(Command::Back));
+        assert_eq!(parse_command(":?"), Ok(Command::Help));
+        assert_eq!(parse_command(":exit"), Ok(Command::Quit));
+        assert_eq!(
+            parse_command(":subject Lord of the Rings"),
+            Ok(Command::Narrow(Field::Subject, "Lord of the Rings"))
+        );
+    }
+
+    #[test]
+    fn open_needs_a_number() {
+        assert_eq!(
+            parse_command(":open"),
+            Err("Use :open with a number shown above.".to_string())
+        );
+        assert!(parse_command(":open two").unwrap_err().contains("`two`"));
+        assert!(parse_command(":open -1").is_err());
+        assert!(parse_command(":open 1 x").is_err());
+        assert!(parse_command(":open 1 2 3").is_err());
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected() {
+        assert_eq!(
+            parse_command(":frobnicate now"),
+            Err("Unknown command `:frobnicate`. Type :help for the list of commands.".to_string())
+        );
+        assert!(
+            parse_command(":content ring").is_err(),
+            "only facets narrow results"
+        );
+        assert!(parse_command(":\x1b[2J").unwrap_err().contains("\\u{1b}"));
+    }
+
+    #[test]
+    fn commands_need_results_first() {
+        with_session(|session| {
+            let first = "Search for something first.".to_string();
+            assert_eq!(session.open(1, None).err(), Some(first.clone()));
+            assert_eq!(session.next(), Err(first.clone()));
+            assert_eq!(session.narrow(Field::Subject, "Bible"), Err(first));
+            assert_eq!(session.back(), Err("Nothing to go back to.".to_string()));
+        });
+    }
+
+    #[test]
+    fn open_takes_a_rank_on_the_current_page() {
+        with_session(|session| {
+            session.search("ring");
+            assert_eq!(session.open(1, None).map(|target| target.doc_id), Ok(0));
+            assert!(session.open(3, None).is_err(), "not on this page");
+            assert!(session.open(0, None).is_err());
+            session.next().unwrap();
+            assert!(session.open(1, None).is_err());
+            assert!(session.open(3, None).is_ok());
+        });
+    }
+
+    #[test]
+    fn next_stops_at_the_last_page_and_back_returns() {
+        with_session(|session| {
+            session.search("ring");
+            session.next().unwrap();
+            let view = session.current().unwrap();
+            assert_eq!(view.page.offset, 2);
+            assert_eq!(view.results.hits.len(), 1);
+            assert_eq!(session.next(), Err("No more results.".to_string()));
+
+            session.back().unwrap();
+            assert_eq!(session.current().unwrap().page.offset, 0);
+            assert_eq!(session.back(), Err("Nothing to go back to.".to_string()));
+        });
+    }
+
+    #[test]
+    fn narrowing_keeps_the_query_and_restarts_paging() {
+        with_session(|session| {
+            session.search("ring");
+            session.next().unwrap();
+            session.narrow(Field::Subject, "bible").unwrap();
+            let view = session.current().unwrap();
+            assert_eq!(view.page.offset, 0);
+            assert_eq!(view.results.total_hits, 2);
+            assert!(view.results.hits.iter().all(|hit| hit.subject == "Bible"));
+            assert_eq!(
+                session.narrow(Field::Subject, "Poetry"),
+                Err("No subject `Poetry` in these results.".to_string())
+            );
+        });
+    }
+}
@@ -0,0 +1,586 @@
+use std::ops::Range;
+
+use serde::Serialize;
+
+use crate::highlighter::Highlighter;
+use crate::proto::{Document, Library};
+use crate::query::{MatchKind, Query};
+use crate::search::{Facet, Field, Hit, Page, TopDocs};
+use crate::sentences::{sentence_starts, sentences};
+
+/// A page of search results with everything needed to show them: renderers
+/// only lay this out and never go back to the index or the library.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchResults {
+    pub query: String,
+    pub total_hits: usize,
+    /// False if `total_hits` is only a lower bound.
+    pub total_hits_exact: bool,
+    pub offset: usize,
+    pub hits: Vec<SearchHit>,
+    pub facets: Vec<Facet>,
+}
+
+/// One result, with its overall rank starting at 1.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchHit {
+    pub id: usize,
+    pub rank: usize,
+    pub name: String,
+    pub subject: String,
+    pub score: f32,
+    /// Matched words in all fields, which may be more than the snippets show.
+    pub hit_count: usize,
+    pub snippets: Vec<Snippet>,
+}
+
+/// Text around a hit, unhighlighted, with the matched words.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Snippet {
+    pub field: Field,
+    /// Word position in the field where the snippet starts.
+    pub position: usize,
+    pub text: String,
+    pub matches: Vec<Match>,
+}
+
+/// The byte range of a matched word in some text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Match {
+    pub start: usize,
+    pub end: usize,
+    pub kind: MatchKind,
+}
+
+/// Limits on the length of content snippets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnippetOptions {
+    pub max_words: usize,
+    pub max_chars: usize,
+    /// Most content snippets shown per document.
+    pub max_snippets: usize,
+    /// Show every sentence with a hit, whole, instead of the best passages.
+    pub sentences: bool,
+}
+
+impl Default for SnippetOptions {
+    fn default() -> Self {
+        SnippetOptions {
+            max_words: 30,
+            max_chars: 200,
+            max_snippets: 3,
+            sentences: false,
+        }
+    }
+}
+
+impl SearchResults {
+    /// Builds the results of `input`, which was run as `query`, for display.
+    pub(crate) fn new(
+        library: &Library,
+        input: &str,
+        query: &Query,
+        top: &TopDocs,
+        page: Page,
+        snippets: SnippetOptions,
+    ) -> SearchResults {
+        let highlighter = Highlighter::new(query);
+        let hits = top
+            .docs
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                let doc = &library.documents[result.doc_id];
+                SearchHit {
+                    id: result.doc_id,
+                    rank: page.offset + i + 1,
+                    name: doc.name.clone(),
+                    subject: doc.subject.clone(),
+                    score: result.score,
+                    hit_count: result.hits.len(),
+                    snippets: doc_snippets(doc, &result.hits, &highlighter, snippets),
+                }
+            })
+            .collect();
+
+        SearchResults {
+            query: input.to_string(),
+            total_hits: top.total_hits,
+            total_hits_exact: top.total_hits_exact,
+            offset: page.offset,
+            hits,
+            facets: top.facets.clone(),
+        }
+    }
+
+    /// The hit with the given overall rank, if it is on this page.
+    pub fn hit(&self, rank: usize) -> Option<&SearchHit> {
+        self.hits.iter().find(|hit| hit.rank == rank)
+    }
+}
+
+impl Snippet {
+    pub fn new(field: Field, position: usize, text: String, highlighter: &Highlighter) -> Snippet {
+        let matches = highlighter.matches(&text);
+        Snippet {
+            field,
+            position,
+            text,
+            matches,
+        }
+    }
+
+    /// The text split into pieces, with the kind of match of matched ones.
+    pub fn segments(&self) -> Vec<(&str, Option<MatchKind>)> {
+        segments(&self.text, &self.matches)
+    }
+}
+
+/// Splits `text` at the given sorted, non-overlapping matches into pieces,
+/// with the kind of match of the pieces that matched.
+pub fn segments<'a>(text: &'a str, matches: &[Match]) -> Vec<(&'a str, Option<MatchKind>)> {
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for m in matches {
+        if m.start > last {
+            segments.push((&text[last..m.start], None));
+        }
+        segments.push((&text[m.start..m.end], Some(m.kind)));
+        last = m.end;
+    }
+    if last < text.len() {
+        segments.push((&text[last..], None));
+    }
+    segments
+}
+
+/// The snippets of one document: the best passages around its content hits,
+/// or the sentences holding them, followed by the value of every other field
+/// with a hit, once per field.
+fn doc_snippets(
+    doc: &Document,
+    hits: &[Hit],
+    highlighter: &Highlighter,
+    options: SnippetOptions,
+) -> Vec<Snippet> {
+    let content_hits: Vec<usize> = hits
+        .iter()
+        .filter(|hit| hit.field == Field::Content)
+        .map(|hit| hit.pos)
+        .collect();
+    let mut passages = if options.sentences {
+        hit_sentences(&doc.content, &content_hits)
+    } else {
+        best_passages(&doc.content, &content_hits, options)
+    };
+    passages.truncate(options.max_snippets);
+    let mut fields: Vec<Field> = Vec::new();
+    for hit in hits {
+        if hit.field != Field::Content && !fields.contains(&hit.field) {
+            fields.push(hit.field);
+        }
+    }
+    let metadata = fields
+        .into_iter()
+        .map(|field| (field, 0, field.value(doc).to_string()));
+
+    passages
+        .into_iter()
+        .map(|(position, text)| (Field::Content, position, text))
+        .chain(metadata)
+        .map(|(field, position, text)| Snippet::new(field, position, text, highlighter))
+        .collect()
+}
+
+/// Picks up to `max_snippets` passages of `content` around the hits at word
+/// positions `hits`. Every hit proposes its best window within the snippet
+/// limits; the highest scoring windows not covering an already shown hit are
+/// kept and merged where they overlap. Ellipses mark where the content was cut.
+/// Passages come with the word position they start at.
+fn best_passages(content: &str, hits: &[usize], options: SnippetOptions) -> Vec<(usize, String)> {
+    let words: Vec<&str> = content.split_whitespace().collect();
+    let mut hits: Vec<usize> = hits
+        .iter()
+        .copied()
+        .filter(|&pos| pos < words.len())
+        .collect();
+    hits.sort_unstable();
+    hits.dedup();
+    if hits.is_empty() {
+        return Vec::new();
+    }
+
+    let starts = sentence_starts(&words);
+    let mut candidates: Vec<(f32, usize, Range<usize>)> = hits
+        .iter()
+        .map(|&hit| {
+            let (score, window) = best_window(&words, &starts, &hits, hit, options);
+            (score, hit, window)
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+    let mut chosen: Vec<Range<usize>> = Vec::new();
+    for (_, hit, window) in candidates {
+        if chosen.len() == options.max_snippets {
+            break;
+        }
+        if !chosen.iter().any(|w| w.contains(&hit)) {
+            chosen.push(window);
+        }
+    }
+    chosen.sort_by_key(|window| window.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for window in chosen {
+        match merged.last_mut() {
+            Some(last) if window.start <= last.end => last.end = last.end.max(window.end),
+            _ => merged.push(window),
+        }
+    }
+
+    merged
+        .into_iter()
+        .map(|window| {
+            let mut text = words[window.clone()].join(" ");
+            if window.start > 0 {
+                text.insert_str(0, "... ");
+            }
+            if window.end < words.len() {
+                text.push_str(" ...");
+            }
+            (window.start, text)
+        })
+        .collect()
+}
+
+/// The best window containing `hit` of at most `max_words` words and
+/// `max_chars` characters (or just the hit, if that alone is longer), with its
+/// score: the number of hits it holds, plus a little for starting and ending
+/// at sentence boundaries (`starts`, from `sentence_starts`), for using the
+/// whole word budget and for keeping `hit` near the middle.
+fn best_window(
+    words: &[&str],
+    starts: &[bool],
+    hits: &[usize],
+    hit: usize,
+    options: SnippetOptions,
+) -> (f32, Range<usize>) {
+    let max_words = options.max_words.max(1);
+    let mut best = (f32::MIN, hit..hit + 1);
+
+    for start in (hit + 1).saturating_sub(max_words)..=hit {
+        // Grow the window from `start` while it stays within the limits
+        let mut end = start;
+        let mut chars = 0;
+        while end < words.len() && end - start < max_words {
+            let next = chars + words[end].chars().count() + usize::from(end > start);
+            if next > options.max_chars && end > hit {
+                break;
+            }
+            chars = next;
+            end += 1;
+        }
+        if chars > options.max_chars && start < hit {
+            continue;
+        }
+
+        let window = start..end;
+        let density = hits.iter().filter(|pos| window.contains(pos)).count() as f32;
+        let starts_sentence = starts[start];
+        let ends_sentence = starts[end];
+        let fill = window.len() as f32 / max_words as f32;
+        let center = (start + end) as f32 / 2.0;
+        let off_center = (center - hit as f32 - 0.5).abs() / max_words as f32;
+        let score = density
+            + if starts_sentence { 0.5 } else { 0.0 }
+            + if ends_sentence { 0.25 } else { 0.0 }
+            + 0.4 * fill
+            - 0.2 * off_center;
+        if score > best.0 {
+            best = (score, window);
+        }
+    }
+    best
+}
+
+/// Every sentence of `content` holding one of the hits at word positions
+/// `hits`, in document order, with the word position it starts at.
+fn hit_sentences(content: &str, hits: &[usize]) -> Vec<(usize, String)> {
+    let words: Vec<&str> = content.split_whitespace().collect();
+    sentences(&words)
+        .into_iter()
+        .filter(|sentence| hits.iter().any(|pos| sentence.contains(pos)))
+        .map(|sentence| (sentence.start, words[sentence].join(" ")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query;
+    use crate::scoring::Ranking;
+    use crate::search::{create_inverted_index, search};
+
+    #[test]
+    fn segments_alternate_between_matched_and_unmatched_text() {
+        let snippet = Snippet::new(
+            Field::Content,
+            0,
+            "a ring of gold".to_string(),
+            &Highlighter::new(&parse_query("ring gold")),
+        );
+        let direct = Some(MatchKind::Direct);
+        assert_eq!(
+            snippet.segments(),
+            [
+                ("a ", None),
+                ("ring", direct),
+                (" of ", None),
+                ("gold", direct)
+            ]
+        );
+        let ring = |start, end, kind| Match { start, end, kind };
+        assert_eq!(
+            segments(
+                "ring ring",
+                &[
+                    ring(0, 4, MatchKind::Direct),
+                    ring(5, 9, MatchKind::Synonym)
+                ]
+            ),
+            [
+                ("ring", direct),
+                (" ", None),
+                ("ring", Some(MatchKind::Synonym))
+            ]
+        );
+        assert!(segments("", &[]).is_empty());
+    }
+
+    fn numbered_words(count: usize) -> String {
+        let words: Vec<String> = (0..count).map(|i| format!("w{}", i)).collect();
+        words.join(" ")
+    }
+
+    fn limits(max_words: usize, max_chars: usize) -> SnippetOptions {
+        SnippetOptions {
+            max_words,
+            max_chars,
+            max_snippets: 3,
+            sentences: false,
+        }
+    }
+
+    fn passage_texts(content: &str, hits: &[usize], options: SnippetOptions) -> Vec<String> {
+        let passages = best_passages(content, hits, options);
+        passages.into_iter().map(|(_, text)| text).collect()
+    }
+
+    fn sentence_texts(content: &str, hits: &[usize]) -> Vec<String> {
+        let sentences = hit_sentences(content, hits);
+        sentences.into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn passages_know_the_word_they_start_at() {
+        let content = numbered_words(40);
+        let starts: Vec<usize> = best_passages(&content, &[10, 30], limits(5, 200))
+            .into_iter()
+            .map(|(start, _)| start)
+            .collect();
+        assert_eq!(starts, [8, 28]);
+        let content = "Far away. The ring, the band. Nothing here. Another ring!";
+        let starts: Vec<usize> = hit_sentences(content, &[3, 8])
+            .into_iter()
+            .map(|(start, _)| start)
+            .collect();
+        assert_eq!(starts, [2, 8]);
+    }
+
+    #[test]
+    fn short_content_is_shown_whole() {
+        let passages = passage_texts("The One Ring.", &[2], SnippetOptions::default());
+        assert_eq!(passages, ["The One Ring."]);
+        assert!(passage_texts("The One Ring.", &[], SnippetOptions::default()).is_empty());
+        assert!(
+            passage_texts("The One Ring.", &[7], SnippetOptions::default()).is_empty(),
+            "stale positions are ignored"
+        );
+    }
+
+    #[test]
+    fn passages_are_cut_to_the_word_limit_around_the_hit() {
+        let content = numbered_words(100);
+        assert_eq!(
+            passage_texts(&content, &[50], limits(5, 200)),
+            ["... w48 w49 w50 w51 w52 ..."]
+        );
+        assert_eq!(
+            passage_texts(&content, &[0], limits(3, 200)),
+            ["w0 w1 w2 ..."]
+        );
+        assert_eq!(
+            passage_texts(&content, &[99], limits(3, 200)),
+            ["... w97 w98 w99"]
+        );
+    }
+
+    #[test]
+    fn passages_are_cut_to_the_character_limit() {
+        let content = "alpha bravo charlie delta echo foxtrot golf hotel india juliet";
+        let passages = passage_texts(content, &[5], limits(30, 20));
+        assert_eq!(passages.len(), 1);
+        let text = passages[0]
+            .trim_start_matches("... ")
+            .trim_end_matches(" ...");
+        assert!(text.contains("foxtrot"));
+        assert!(text.chars().count() <= 20, "{}", text);
+        assert!(passages[0].starts_with("... ") && passages[0].ends_with(" ..."));
+
+        let long = format!("short {} short", "x".repeat(50));
+        assert_eq!(
+            passage_texts(&long, &[1], limits(30, 10)),
+            [format!("... {} ...", "x".repeat(50))],
+            "a hit longer than the limit is still shown"
+        );
+    }
+
+    #[test]
+    fn windows_prefer_more_hits_and_whole_clauses() {
+        let content = "Far away. The ring and the band, ring again. Then more words follow here";
+        let passages = passage_texts(content, &[3, 7], limits(7, 200));
+        assert_eq!(passages, ["... The ring and the band, ring again. ..."]);
+    }
+
+    #[test]
+    fn overlapping_and_adjacent_passages_are_merged() {
+        let content = numbered_words(40);
+        assert_eq!(
+            passage_texts(&content, &[10, 15], limits(5, 200)),
+            ["... w8 w9 w10 w11 w12 w13 w14 w15 w16 w17 ..."]
+        );
+        assert_eq!(
+            passage_texts(&content, &[10, 30], limits(5, 200)),
+            ["... w8 w9 w10 w11 w12 ...", "... w28 w29 w30 w31 w32 ..."]
+        );
+    }
+
+    #[test]
+    fn max_snippets_caps_the_passages() {
+        let content = numbered_words(100);
+        let hits = [5, 25, 45, 65, 85];
+        let mut options = limits(3, 200);
+        assert_eq!(passage_texts(&content, &hits, options).len(), 3);
+        options.max_snippets = 1;
+        assert_eq!(
+            passage_texts(&content, &hits, options),
+            ["... w4 w5 w6 ..."]
+        );
+        options.max_snippets = 0;
+        assert!(passage_texts(&content, &hits, options).is_empty());
+        options.max_snippets = 10;
+        assert_eq!(passage_texts(&content, &hits, options).len(), 5);
+    }
+
+    #[test]
+    fn sentence_mode_shows_the_sentences_holding_hits() {
+        let content = "Far away. The ring, the band. Nothing here. Another ring!";
+        assert_eq!(
+            sentence_texts(content, &[3, 8]),
+            ["The ring, the band.", "Another ring!"]
+        );
+        assert_eq!(
+            sentence_texts(content, &[1, 2]),
+            ["Far away.", "The ring, the band."]
+        );
+    }
+
+    fn document(name: &str, subject: &str, content: &str) -> Document {
+        let mut doc = Document::new();
+        doc.set_name(name.to_string());
+        doc.set_subject(subject.to_string());
+        doc.set_content(content.to_string());
+        doc
+    }
+
+    fn hit(field: Field, pos: usize) -> Hit {
+        Hit {
+            doc_id: 0,
+            field,
+            pos,
+        }
+    }
+
+    #[test]
+    fn every_field_is_shown_once_after_the_content() {
+        let doc = document(
+            "Ring of rings",
+            "Ring lore",
+            "A ring. Far away, another ring.",
+        );
+        let hits = [
+            hit(Field::Name, 2),
+            hit(Field::Content, 1),
+            hit(Field::Name, 0),
+            hit(Field::Subject, 0),
+            hit(Field::Content, 5),
+        ];
+        let highlighter = Highlighter::new(&parse_query("ring rings"));
+        let snippets = doc_snippets(&doc, &hits, &highlighter, SnippetOptions::default());
+        let shown: Vec<(Field, &str)> = snippets
+            .iter()
+            .map(|snippet| (snippet.field, snippet.text.as_str()))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                (Field::Content, "A ring. Far away, another ring."),
+                (Field::Name, "Ring of rings"),
+                (Field::Subject, "Ring lore"),
+            ]
+        );
+        assert_eq!(snippets[1].matches.len(), 2);
+
+        let options = SnippetOptions {
+            max_snippets: 0,
+            ..SnippetOptions::default()
+        };
+        let snippets = doc_snippets(&doc, &hits, &highlighter, options);
+        assert_eq!(snippets.len(), 2, "fields are shown even without passages");
+
+        let options = SnippetOptions {
+            max_snippets: 1,
+            sentences: true,
+            ..SnippetOptions::default()
+        };
+        let snippets = doc_snippets(&doc, &hits, &highlighter, options);
+        assert_eq!(snippets[0].text, "A ring.");
+        assert_eq!(snippets.len(), 3);
+    }
+
+    #[test]
+    fn hit_count_counts_every_hit() {
+        let mut library = Library::new();
+        library.mut_documents().push(document(
+            "Ring",
+            "Lore",
+            &format!("ring {}", "ring ".repeat(20)),
+        ));
+        let index = create_inverted_index(&library);
+        let query = parse_query("ring");
+        let top = search(&index, &query, &Ranking::default(), Page::default());
+        let results = SearchResults::new(
+            &library,
+            "ring",
+            &query,
+            &top,
+            Page::default(),
+            SnippetOptions::default(),
+        );
+        assert_eq!(results.hits[0].hit_count, 22);
+        assert_eq!(results.hits[0].snippets.len(), 2);
+        assert_eq!(results.hit(1), Some(&results.hits[0]));
+        assert_eq!(results.hit(2), None);
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::search::Field;
+
+/// Collection wide statistics of one indexed field.
+#[derive(Debug, Clone, Copy)]
+pub struct FieldStats {
+    pub doc_count: usize,
+    pub avg_length: f32,
+}
+
+/// Scores a term (or phrase) occurring `tf` times in a field of `doc_length`
+/// tokens, given that it occurs in `doc_freq` documents overall.
+///
+/// Implementations only see one field of one document; field and subject
+/// boosts are applied on top by `Ranking`.
+pub trait Scorer: Send + Sync {
+    fn score(&self, tf: usize, doc_length: usize, doc_freq: usize, stats: &FieldStats) -> f32;
+
+    /// An upper bound of `score` for postings with at most `max_tf` occurrences
+    /// in fields of at least `min_length` tokens, used to skip documents that
+    /// cannot make the top results. The default assumes scores never decrease
+    /// with `tf` nor increase with length, as holds for BM25 and TF-IDF.
+    fn max_score(
+        &self,
+        max_tf: usize,
+        min_length: usize,
+        doc_freq: usize,
+        stats: &FieldStats,
+    ) -> f32 {
+        self.score(max_tf, min_length, doc_freq, stats)
+    }
+}
+
+/// Okapi BM25.
+pub struct Bm25 {
+    /// Term frequency saturation: how quickly repeated matches stop adding score.
+    pub k1: f32,
+    /// How strongly scores are normalized by field length, from 0 (not at all) to 1.
+    pub b: f32,
+}
+
+impl Default for Bm25 {
+    fn default() -> Self {
+        Bm25 { k1: 1.2, b: 0.75 }
+    }
+}
+
+impl Scorer for Bm25 {
+    fn score(&self, tf: usize, doc_length: usize, doc_freq: usize, stats: &FieldStats) -> f32 {
+        let tf = tf as f32;
+        let length_norm = 1.0 - self.b + self.b * doc_length as f32 / stats.avg_length.max(1.0);
+        bm25_idf(doc_freq, stats) * tf * (self.k1 + 1.0) / (tf + self.k1 * length_norm)
+    }
+}
+
+/// Inverse document frequency, BM25 flavour: never negative, even for terms
+/// present in more than half of the documents.
+pub fn bm25_idf(doc_freq: usize, stats: &FieldStats) -> f32 {
+    let n = stats.doc_count as f32;
+    let df = doc_freq as f32;
+    (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+}
+
+/// Classic Lucene style TF-IDF: `sqrt(tf) * idf^2 / sqrt(doc_length)`.
+pub struct TfIdf;
+
+impl Scorer for TfIdf {
+    fn score(&self, tf: usize, doc_length: usize, doc_freq: usize, stats: &FieldStats) -> f32 {
+        let idf = 1.0 + (stats.doc_count as f32 / (doc_freq as f32 + 1.0)).ln();
+        (tf as f32).sqrt() * idf * idf / (doc_length.max(1) as f32).sqrt()
+    }
+}
+
+/// Names accepted by `scorer_by_name`. Register new scorers in both places.
+pub const SCORERS: &[&str] = &["bm25", "tfidf"];
+
+pub fn scorer_by_name(name: &str) -> Option<Box<dyn Scorer>> {
+    match name.to_ascii_lowercase().as_str() {
+        "bm25" => Some(Box::new(Bm25::default())),
+        "tfidf" | "tf-idf" => Some(Box::new(TfIdf)),
+        _ => None,
+    }
+}
+
+/// How search results are scored: the scorer plus boosts multiplied into the
+/// score of matches in a given field, or of documents with a given subject.
+/// Boosts are never negative, so scores are not either, as pruning assumes.
+pub struct Ranking {
+    pub scorer: Box<dyn Scorer>,
+    field_boosts: HashMap<Field, f32>,
+    /// Keyed by lowercased subject.
+    pub(crate) subject_boosts: HashMap<String, f32>,
+}
+
+/// A boost that is negative, infinite or NaN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidBoost(pub f32);
+
+impl fmt::Display for InvalidBoost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "boost must be a finite number of at least 0, got {}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidBoost {}
+
+impl Default for Ranking {
+    /// BM25, with a match in a document's name worth twice a match elsewhere.
+    fn default() -> Self {
+        Ranking {
+            scorer: Box::new(Bm25::default()),
+            field_boosts: HashMap::from([(Field::Name, 2.0)]),
+            subject_boosts: HashMap::new(),
+        }
+    }
+}
+
+impl Ranking {
+    /// Multiplies the score of matches in `field` by `boost`.
+    pub fn boost_field(&mut self, field: Field, boost: f32) -> Result<(), InvalidBoost> {
+        self.field_boosts.insert(field, check_boost(boost)?);
+        Ok(())
+    }
+
+    /// Multiplies the score of documents with `subject`, in any case, by `boost`.
+    pub fn boost_subject(&mut self, subject: &str, boost: f32) -> Result<(), InvalidBoost> {
+        self.subject_boosts
+            .insert(subject.to_lowercase(), check_boost(boost)?);
+        Ok(())
+    }
+
+    pub fn field_boost(&self, field: Field) -> f32 {
+        self.field_boosts.get(&field).copied().unwrap_or(1.0)
+    }
+
+    pub fn subject_boost(&self, subject: &str) -> f32 {
+        self.subject_boosts
+            .get(&subject.to_lowercase())
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+fn check_boost(boost: f32) -> Result<f32, InvalidBoost> {
+    if boost.is_finite() && boost >= 0.0 {
+        Ok(boost)
+    } else {
+        Err(InvalidBoost(boost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATS: FieldStats = FieldStats {
+        doc_count: 10,
+        avg_length: 10.0,
+    };
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    fn bm25(tf: usize, doc_length: usize) -> f32 {
+        Bm25::default().score(tf, doc_length, 1, &STATS)
+    }
+
+    #[test]
+    fn bm25_idf_falls_with_document_frequency_but_stays_positive() {
+        // ln(1 + (10 - 1 + 0.5) / (1 + 0.5))
+        assert_close(bm25_idf(1, &STATS), 1.992_430_2);
+        assert_close(bm25_idf(5, &STATS), std::f32::consts::LN_2);
+        assert_close(bm25_idf(10, &STATS), 0.046_520_02);
+    }
+
+    #[test]
+    fn bm25_at_average_length_with_one_occurrence_is_the_idf() {
+        assert_close(bm25(1, 10), bm25_idf(1, &STATS));
+    }
+
+    #[test]
+    fn bm25_saturates_repeated_occurrences_with_k1() {
+        // idf * 2 * (1.2 + 1) / (2 + 1.2)
+        assert_close(bm25(2, 10), 2.739_591_5);
+        // Never more than idf * (k1 + 1)
+        assert!(bm25(1000, 10) < bm25_idf(1, &STATS) * 2.2);
+        assert_close(bm25(1000, 10), 4.378_093);
+        // Without saturation every occurrence counts the same
+        let flat = Bm25 { k1: 0.0, b: 0.75 };
+        assert_close(flat.score(7, 10, 1, &STATS), bm25_idf(1, &STATS));
+    }
+
+    #[test]
+    fn bm25_normalizes_length_with_b() {
+        // Length norms 1 - 0.75 + 0.75 * 20 / 10 = 1.75 and 0.625
+        assert_close(bm25(1, 20), 1.413_982_7);
+        assert_close(bm25(1, 5), 2.504_769_3);
+        let unnormalized = Bm25 { k1: 1.2, b: 0.0 };
+        assert_close(unnormalized.score(1, 20, 1, &STATS), bm25_idf(1, &STATS));
+    }
+
+    #[test]
+    fn tf_idf_squares_the_idf_and_divides_by_the_root_length() {
+        // idf = 1 + ln(10 / (1 + 1)); sqrt(4) * idf^2 / sqrt(16)
+        assert_close(TfIdf.score(4, 16, 1, &STATS), 3.404_583);
+        assert_close(TfIdf.score(1, 1, 1, &STATS), 6.809_166);
+        // 1 + ln(10 / 11) is still positive
+        assert_close(TfIdf.score(1, 1, 10, &STATS), 0.818_463_6);
+        assert_close(TfIdf.score(1, 0, 1, &STATS), 6.809_166);
+    }
+
+    #[test]
+    fn max_score_bounds_every_score_it_covers() {
+        let scorers: [Box<dyn Scorer>; 4] = [
+            Box::new(Bm25::default()),
+            Box::new(Bm25 { k1: 2.0, b: 1.0 }),
+            Box::new(Bm25 { k1: 0.5, b: 0.0 }),
+            Box::new(TfIdf),
+        ];
+        for scorer in &scorers {
+            for doc_freq in [1, 4, 10] {
+                let bound = scorer.max_score(5, 3, doc_freq, &STATS);
+                for tf in 1..=5 {
+                    for doc_length in 3..40 {
+                        let score = scorer.score(tf, doc_length, doc_freq, &STATS);
+                        assert!(
+                            score <= bound,
+                            "tf {} length {} df {}: {} > {}",
+                            tf,
+                            doc_length,
+                            doc_freq,
+                            score,
+                            bound
+                        );
+                    }
+                }
+                assert_eq!(bound, scorer.score(5, 3, doc_freq, &STATS));
+            }
+        }
+    }
+
+    #[test]
+    fn scorers_are_found_by_name() {
+        for name in SCORERS {
+            assert!(scorer_by_name(name).is_some(), "{}", name);
+        }
+        assert!(scorer_by_name("TF-IDF").is_some());
+        assert!(scorer_by_name("pagerank").is_none());
+    }
+
+    #[test]
+    fn boosts_default_to_one() {
+        let mut ranking = Ranking::default();
+        assert_eq!(ranking.field_boost(Field::Name), 2.0);
+        assert_eq!(ranking.field_boost(Field::Content), 1.0);
+        ranking.boost_subject("Bible", 3.0).unwrap();
+        assert_eq!(ranking.subject_boost("Bible"), 3.0);
+        assert_eq!(ranking.subject_boost("Poetry"), 1.0);
+    }
+
+    #[test]
+    fn boosts_must_be_finite_and_not_negative() {
+        let mut ranking = Ranking::default();
+        for boost in [-1.0, f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(ranking.boost_field(Field::Content, boost).is_err());
+            assert!(ranking.boost_subject("Bible", boost).is_err());
+        }
+        assert_eq!(ranking.field_boost(Field::Content), 1.0);
+        assert_eq!(ranking.subject_boost("Bible"), 1.0);
+
+        ranking.boost_field(Field::Content, 0.0).unwrap();
+        ranking.boost_subject("Bible", 1.5).unwrap();
+        assert_eq!(ranking.field_boost(Field::Content), 0.0);
+        assert_eq!(ranking.subject_boost("BIBLE"), 1.5);
+    }
+}
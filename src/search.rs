@@ -1,87 +1,1126 @@
-use std::collections::HashMap;
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::collections::{HashMap, HashSet};
 
 use rayon::prelude::*;
+use serde::Serialize;
 
-use crate::proto::Library;
+use crate::collector::TopK;
+use crate::maxscore;
+use crate::proto::{Document, Library};
+use crate::query::{parse_query, Clause, Query, QueryNode};
+use crate::scoring::{FieldStats, Ranking};
+use crate::thesaurus::{expand_synonyms, Thesaurus};
 use crate::utils::tokenize;
 
-const THESAURUS_FILE: &str = "thesaurus.txt";
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Name,
+    Subject,
+    Content,
+}
+
+impl Field {
+    pub const ALL: [Field; 3] = [Field::Name, Field::Subject, Field::Content];
+    /// Metadata fields results are counted by, to summarize and narrow them.
+    pub const FACETS: [Field; 1] = [Field::Subject];
 
-pub fn create_inverted_index(library: &Library) -> HashMap<String, Vec<(usize, usize)>> {
-    let mut index = HashMap::new();
-    for (doc_id, doc) in library.documents.iter().enumerate() {
-        for (pos, word) in tokenize(&doc.content).iter().enumerate() {
-            index
-                .entry(word.clone())
-                .or_insert(vec![])
-                .push((doc_id, pos));
+    pub fn name(self) -> &'static str {
+        match self {
+            Field::Name => "name",
+            Field::Subject => "subject",
+            Field::Content => "content",
         }
     }
-    index
+
+    pub fn from_name(name: &str) -> Option<Field> {
+        Field::ALL
+            .iter()
+            .copied()
+            .find(|field| field.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the field holds short metadata (as opposed to the document body).
+    pub fn is_metadata(self) -> bool {
+        self != Field::Content
+    }
+
+    pub fn value(self, doc: &Document) -> &str {
+        match self {
+            Field::Name => &doc.name,
+            Field::Subject => &doc.subject,
+            Field::Content => &doc.content,
+        }
+    }
+}
+
+/// A single match of a query term: the document, the field it was found in and
+/// the word position within that field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hit {
+    pub doc_id: usize,
+    pub field: Field,
+    pub pos: usize,
 }
 
-pub fn fetch_synonyms(word: &str, thesaurus: &HashMap<String, Vec<String>>) -> Vec<String> {
-    thesaurus
-        .get(word)
-        .cloned()
-        .unwrap_or_else(|| vec![word.to_string()])
+/// A matching document with its relevance score and the hits to show for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredDoc {
+    pub doc_id: usize,
+    pub score: f32,
+    pub hits: Vec<Hit>,
 }
 
-pub fn load_thesaurus() -> io::Result<HashMap<String, Vec<String>>> {
-    let mut thesaurus = HashMap::new();
-    let file = File::open(THESAURUS_FILE)?;
-    for line in io::BufReader::new(file).lines() {
-        let line = line?;
-        let mut parts = line.split(',');
-        if let Some(word) = parts.next() {
-            thesaurus.insert(word.to_string(), parts.map(|s| s.to_string()).collect());
+/// The (doc_id, positions) postings of one term, sorted by doc_id, with the
+/// largest term frequency and shortest field among them. Together these bound
+/// the score any single posting can reach.
+#[derive(Debug, Default)]
+pub struct PostingList {
+    pub postings: Vec<(usize, Vec<usize>)>,
+    pub max_tf: usize,
+    pub min_length: usize,
+}
+
+/// The postings of one field, plus the token count of the field in every
+/// document for length normalization. Metadata fields also keep their raw values.
+#[derive(Default)]
+struct FieldIndex {
+    terms: HashMap<String, PostingList>,
+    lengths: Vec<usize>,
+    total_length: usize,
+    values: Vec<String>,
+}
+
+/// An inverted index kept separately for every indexed field.
+pub struct InvertedIndex {
+    fields: HashMap<Field, FieldIndex>,
+    /// Documents with any text, leaving out the empty ones `Library::remove`
+    /// leaves behind, which would skew the field statistics.
+    live_docs: usize,
+}
+
+impl InvertedIndex {
+    pub fn posting_list(&self, field: Field, term: &str) -> Option<&PostingList> {
+        self.fields
+            .get(&field)
+            .and_then(|index| index.terms.get(term))
+    }
+
+    pub fn postings(&self, field: Field, term: &str) -> &[(usize, Vec<usize>)] {
+        self.posting_list(field, term)
+            .map_or(&[], |list| list.postings.as_slice())
+    }
+
+    /// Number of tokens in `field` of document `doc_id`.
+    pub fn field_length(&self, field: Field, doc_id: usize) -> usize {
+        self.fields[&field].lengths[doc_id]
+    }
+
+    /// The stored value of a metadata field (name or subject) of a document.
+    pub fn field_value(&self, field: Field, doc_id: usize) -> &str {
+        self.fields[&field]
+            .values
+            .get(doc_id)
+            .map_or("", |value| value.as_str())
+    }
+
+    /// Number of documents indexed.
+    pub fn doc_count(&self) -> usize {
+        self.fields[&Field::Content].lengths.len()
+    }
+
+    pub fn field_stats(&self, field: Field) -> FieldStats {
+        let index = &self.fields[&field];
+        FieldStats {
+            doc_count: self.live_docs,
+            avg_length: index.total_length as f32 / self.live_docs.max(1) as f32,
         }
     }
-    Ok(thesaurus)
 }
 
-pub fn parallel_search(
-    index: &HashMap<String, Vec<(usize, usize)>>,
-    query: &str,
-) -> Vec<(usize, usize)> {
-    static EMPTY_VEC: Vec<(usize, usize)> = vec![];
-    tokenize(query)
-        .par_iter()
-        .flat_map(|word| index.get(word).unwrap_or(&EMPTY_VEC))
-        .cloned()
-        .collect()
+pub(crate) fn create_inverted_index(library: &Library) -> InvertedIndex {
+    let mut fields = HashMap::new();
+    for field in Field::ALL {
+        let mut index = FieldIndex::default();
+        for (doc_id, doc) in library.documents.iter().enumerate() {
+            let tokens = tokenize(field.value(doc));
+            index.lengths.push(tokens.len());
+            index.total_length += tokens.len();
+            if field.is_metadata() {
+                index.values.push(field.value(doc).to_string());
+            }
+            for (pos, word) in tokens {
+                let list = index.terms.entry(word).or_default();
+                match list.postings.last_mut() {
+                    Some((last_doc, positions)) if *last_doc == doc_id => positions.push(pos),
+                    _ => list.postings.push((doc_id, vec![pos])),
+                }
+            }
+        }
+        for list in index.terms.values_mut() {
+            list.max_tf = list
+                .postings
+                .iter()
+                .map(|(_, p)| p.len())
+                .max()
+                .unwrap_or(0);
+            list.min_length = list
+                .postings
+                .iter()
+                .map(|(doc_id, _)| index.lengths[*doc_id])
+                .min()
+                .unwrap_or(0);
+        }
+        fields.insert(field, index);
+    }
+    let live_docs = library
+        .documents
+        .iter()
+        .filter(|doc| Field::ALL.iter().any(|field| !field.value(doc).is_empty()))
+        .count();
+    InvertedIndex { fields, live_docs }
+}
+
+/// Which slice of the ranked results to return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Page {
+    /// Page `number` of results, counting from 1, or `None` if its offset does
+    /// not fit in a `usize`.
+    pub fn numbered(number: usize, limit: usize) -> Option<Page> {
+        let offset = number.checked_sub(1)?.checked_mul(limit)?;
+        Some(Page { offset, limit })
+    }
+
+    /// The number of best results needed to fill the page.
+    pub fn end(self) -> usize {
+        self.offset.saturating_add(self.limit)
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page {
+            offset: 0,
+            limit: 10,
+        }
+    }
+}
+
+/// One page of ranked results, plus the number of documents that matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopDocs {
+    pub total_hits: usize,
+    /// False if `total_hits` is a lower bound because pruning skipped documents.
+    pub total_hits_exact: bool,
+    pub docs: Vec<ScoredDoc>,
+    /// Counts of all matching documents per facet value, if requested.
+    pub facets: Vec<Facet>,
+}
+
+/// How many matching documents have each value of a metadata field, most
+/// frequent first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Facet {
+    pub field: Field,
+    pub counts: Vec<FacetCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+impl Facet {
+    /// The facet value equal to `value`, ignoring case.
+    pub fn find(&self, value: &str) -> Option<&str> {
+        self.counts
+            .iter()
+            .map(|c| c.value.as_str())
+            .find(|v| v.eq_ignore_ascii_case(value.trim()))
+    }
 }
 
+/// Parses `query`, expands it with synonyms and runs it, narrowed to documents
+/// with every facet value in `filters`. The expanded query is returned
+/// alongside the results so callers can highlight the synonyms too.
 pub fn search_with_synonyms(
-    index: &HashMap<String, Vec<(usize, usize)>>,
+    index: &InvertedIndex,
     query: &str,
-    thesaurus: &HashMap<String, Vec<String>>,
-) -> Vec<(usize, usize)> {
-    let synonyms = fetch_synonyms(query, thesaurus);
-    let mut results = search(index, query);
+    filters: &[(Field, String)],
+    thesaurus: &Thesaurus,
+    ranking: &Ranking,
+    page: Page,
+) -> (Query, TopDocs) {
+    let mut query = expand_synonyms(&parse_query(query), thesaurus);
+    query.filters.extend_from_slice(filters);
+    let results = search_faceted(index, &query, ranking, page);
+    (query, results)
+}
+
+/// Per-document score of one query node.
+type DocScores = HashMap<usize, f32>;
+
+/// Runs a parsed query against the index and returns the requested page of
+/// the best matches.
+///
+/// Field-scoped clauses (`subject:Bible`) are filters every result must satisfy;
+/// the remaining clauses are OR'ed together and provide the hits that are shown.
+/// A query made up only of scoped clauses returns the hits of those clauses.
+/// Every matching clause adds to the document score, which is then multiplied
+/// by the boost of the document's subject.
+///
+/// Evaluation is document at a time with MaxScore pruning, so documents that
+/// cannot reach the requested page are skipped without being fully scored;
+/// `total_hits` is then only a lower bound. Hits are gathered for the documents
+/// on the returned page alone.
+pub fn search(index: &InvertedIndex, query: &Query, ranking: &Ranking, page: Page) -> TopDocs {
+    let collected = maxscore::collect_top(index, query, ranking, top_k(index, page));
+    page_of(index, query, collected, page)
+}
+
+/// Like `search`, but also counts the matching documents by every field in
+/// `Field::FACETS`. The counts come from a pass over every match that only
+/// looks up which documents match, without scoring them, so `total_hits` is
+/// exact.
+pub fn search_faceted(
+    index: &InvertedIndex,
+    query: &Query,
+    ranking: &Ranking,
+    page: Page,
+) -> TopDocs {
+    let mut results = search(index, query, ranking, page);
+    let matches = matching_doc_ids(index, query);
+    results.total_hits = matches.len();
+    results.total_hits_exact = true;
+    results.facets = Field::FACETS
+        .iter()
+        .map(|&field| facet_counts(index, field, &matches))
+        .collect();
+    results
+}
+
+fn facet_counts(index: &InvertedIndex, field: Field, matches: &[usize]) -> Facet {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for &doc_id in matches {
+        *counts.entry(index.field_value(field, doc_id)).or_insert(0) += 1;
+    }
+    let mut counts: Vec<FacetCount> = counts
+        .into_iter()
+        .map(|(value, count)| FacetCount {
+            value: value.to_string(),
+            count,
+        })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+    Facet { field, counts }
+}
+
+/// Like `search`, but scores every matching document term at a time. Slower,
+/// but `total_hits` is always exact.
+pub fn search_exhaustive(
+    index: &InvertedIndex,
+    query: &Query,
+    ranking: &Ranking,
+    page: Page,
+) -> TopDocs {
+    let matches = matching_docs(index, query, ranking);
+    let mut top = TopK::new(top_k(index, page));
+    for &(doc_id, score) in &matches {
+        top.push(doc_id, score);
+    }
+
+    let collected = Collected {
+        top,
+        total_hits: matches.len(),
+        exact: true,
+    };
+    page_of(index, query, collected, page)
+}
+
+/// How many of the best documents to collect for `page`: no more than are
+/// indexed, however large the page asked for.
+fn top_k(index: &InvertedIndex, page: Page) -> usize {
+    page.end().min(index.doc_count())
+}
+
+/// Every document matching `query` with its final score, in no particular order.
+fn matching_docs(index: &InvertedIndex, query: &Query, ranking: &Ranking) -> Vec<(usize, f32)> {
+    let clause_scores: Vec<DocScores> = query
+        .clauses
+        .par_iter()
+        .map(|clause| match_clause(index, clause, ranking))
+        .collect();
+
+    let (scoped, unscoped): (Vec<_>, Vec<_>) = query
+        .clauses
+        .iter()
+        .zip(&clause_scores)
+        .partition(|(clause, _)| clause.field.is_some());
+    let candidates = if unscoped.is_empty() {
+        &scoped
+    } else {
+        &unscoped
+    };
+
     let mut seen = HashSet::new();
-    seen.extend(results.iter().cloned());
-
-    for synonym in synonyms {
-        let syn_results = search(index, &synonym);
-        for res in syn_results {
-            if !seen.contains(&res) {
-                results.push(res);
-                seen.insert(res);
+    let mut matches = Vec::new();
+    for (_, scores) in candidates {
+        for &doc_id in scores.keys() {
+            if !seen.insert(doc_id)
+                || !scoped.iter().all(|(_, s)| s.contains_key(&doc_id))
+                || !passes_filters(index, query, doc_id)
+            {
+                continue;
             }
+            let score: f32 = clause_scores.iter().filter_map(|s| s.get(&doc_id)).sum();
+            let subject = index.field_value(Field::Subject, doc_id);
+            matches.push((doc_id, score * ranking.subject_boost(subject)));
         }
     }
-    results
+    matches
+}
+
+/// Every document matching `query`, the same as `matching_docs` finds, but
+/// without scoring them.
+fn matching_doc_ids(index: &InvertedIndex, query: &Query) -> Vec<usize> {
+    let (scoped, unscoped): (Vec<_>, Vec<_>) = query
+        .clauses
+        .iter()
+        .partition(|clause| clause.field.is_some());
+    let scoped: Vec<Vec<bool>> = scoped
+        .into_iter()
+        .map(|clause| clause_docs(index, &[clause]))
+        .collect();
+    let candidates = if unscoped.is_empty() {
+        let mut candidates = vec![false; index.doc_count()];
+        for docs in &scoped {
+            candidates.iter_mut().zip(docs).for_each(|(c, d)| *c |= d);
+        }
+        candidates
+    } else {
+        clause_docs(index, &unscoped)
+    };
+
+    (0..candidates.len())
+        .filter(|&doc_id| {
+            candidates[doc_id]
+                && scoped.iter().all(|docs| docs[doc_id])
+                && passes_filters(index, query, doc_id)
+        })
+        .collect()
+}
+
+/// Which documents match any of `clauses` in any of their fields, by doc_id.
+fn clause_docs(index: &InvertedIndex, clauses: &[&Clause]) -> Vec<bool> {
+    let mut docs = vec![false; index.doc_count()];
+    for clause in clauses {
+        for field in clause_fields(clause) {
+            node_docs(index, field, &clause.node, &mut docs);
+        }
+    }
+    docs
+}
+
+fn node_docs(index: &InvertedIndex, field: Field, node: &QueryNode, docs: &mut [bool]) {
+    match node {
+        QueryNode::Term(term) => {
+            for (doc_id, _) in index.postings(field, term) {
+                docs[*doc_id] = true;
+            }
+        }
+        QueryNode::Phrase(terms) => {
+            for (doc_id, _) in phrase_occurrences(index, field, terms) {
+                docs[doc_id] = true;
+            }
+        }
+        QueryNode::Or(nodes) => {
+            for node in nodes {
+                node_docs(index, field, node, docs);
+            }
+        }
+        QueryNode::Boost(node, _) => node_docs(index, field, node, docs),
+    }
+}
+
+/// Whether a document has every metadata value the query is filtered by.
+pub fn passes_filters(index: &InvertedIndex, query: &Query, doc_id: usize) -> bool {
+    query.filters.iter().all(|(field, value)| {
+        index
+            .field_value(*field, doc_id)
+            .eq_ignore_ascii_case(value)
+    })
+}
+
+/// The outcome of scoring: the best documents and how many matched overall.
+pub struct Collected {
+    pub top: TopK,
+    pub total_hits: usize,
+    /// False if documents may have been skipped without being counted.
+    pub exact: bool,
+}
+
+/// Turns collected documents into the requested page, with hits.
+fn page_of(index: &InvertedIndex, query: &Query, collected: Collected, page: Page) -> TopDocs {
+    let docs = collected
+        .top
+        .into_sorted()
+        .into_iter()
+        .skip(page.offset)
+        .map(|(doc_id, score)| ScoredDoc {
+            doc_id,
+            score,
+            hits: collect_hits(index, query, doc_id),
+        })
+        .collect();
+    TopDocs {
+        total_hits: collected.total_hits,
+        total_hits_exact: collected.exact,
+        docs,
+        facets: Vec::new(),
+    }
+}
+
+/// The clauses whose hits are shown: the unscoped ones, or the field-scoped
+/// ones if the query has nothing else.
+pub fn shown_clauses(query: &Query) -> Vec<&Clause> {
+    let unscoped: Vec<&Clause> = query.clauses.iter().filter(|c| c.field.is_none()).collect();
+    if unscoped.is_empty() {
+        query.clauses.iter().collect()
+    } else {
+        unscoped
+    }
+}
+
+fn match_clause(index: &InvertedIndex, clause: &Clause, ranking: &Ranking) -> DocScores {
+    let mut scores = DocScores::new();
+    for field in clause_fields(clause) {
+        let boost = QueryNode::Boost(Box::new(clause.node.clone()), ranking.field_boost(field));
+        merge_scores(&mut scores, match_node(index, field, &boost, ranking));
+    }
+    scores
+}
+
+pub fn clause_fields(clause: &Clause) -> Vec<Field> {
+    match clause.field {
+        Some(field) => vec![field],
+        None => Field::ALL.to_vec(),
+    }
+}
+
+/// Adds the scores of `other` into `scores`.
+fn merge_scores(scores: &mut DocScores, other: DocScores) {
+    for (doc_id, score) in other {
+        *scores.entry(doc_id).or_insert(0.0) += score;
+    }
+}
+
+fn match_node(
+    index: &InvertedIndex,
+    field: Field,
+    node: &QueryNode,
+    ranking: &Ranking,
+) -> DocScores {
+    match node {
+        QueryNode::Term(term) => {
+            let postings = index.postings(field, term);
+            let frequencies = postings.iter().map(|(doc_id, p)| (*doc_id, p.len()));
+            score_frequencies(index, field, frequencies, ranking)
+        }
+        QueryNode::Phrase(terms) => {
+            let frequencies = phrase_occurrences(index, field, terms).map(|(d, p)| (d, p.len()));
+            score_frequencies(index, field, frequencies, ranking)
+        }
+        QueryNode::Or(nodes) => {
+            let mut scores = DocScores::new();
+            for node in nodes {
+                merge_scores(&mut scores, match_node(index, field, node, ranking));
+            }
+            scores
+        }
+        QueryNode::Boost(node, weight) => {
+            let mut scores = match_node(index, field, node, ranking);
+            for score in scores.values_mut() {
+                *score *= weight;
+            }
+            scores
+        }
+    }
+}
+
+/// Scores the documents a term or phrase occurs in, given how often it occurs
+/// in each of them.
+fn score_frequencies(
+    index: &InvertedIndex,
+    field: Field,
+    frequencies: impl Iterator<Item = (usize, usize)>,
+    ranking: &Ranking,
+) -> DocScores {
+    let frequencies: Vec<(usize, usize)> = frequencies.collect();
+    let stats = index.field_stats(field);
+    let doc_freq = frequencies.len();
+
+    frequencies
+        .into_iter()
+        .map(|(doc_id, tf)| {
+            let doc_length = index.field_length(field, doc_id);
+            let score = ranking.scorer.score(tf, doc_length, doc_freq, &stats);
+            (doc_id, score)
+        })
+        .collect()
+}
+
+/// The hits of the shown clauses in one document, content hits first.
+fn collect_hits(index: &InvertedIndex, query: &Query, doc_id: usize) -> Vec<Hit> {
+    let mut hits: Vec<Hit> = shown_clauses(query)
+        .into_iter()
+        .flat_map(|clause| {
+            clause_fields(clause).into_iter().flat_map(|field| {
+                node_positions(index, field, &clause.node, doc_id)
+                    .into_iter()
+                    .map(move |pos| Hit { doc_id, field, pos })
+            })
+        })
+        .collect();
+    hits.sort_by_key(|hit| (hit.field != Field::Content, hit.pos));
+    hits.dedup();
+    hits
+}
+
+/// Word positions where `node` matches in `field` of one document.
+fn node_positions(
+    index: &InvertedIndex,
+    field: Field,
+    node: &QueryNode,
+    doc_id: usize,
+) -> Vec<usize> {
+    match node {
+        QueryNode::Term(term) => doc_positions(index, field, term, doc_id).to_vec(),
+        QueryNode::Phrase(terms) => {
+            let starts = doc_positions(index, field, &terms[0].1, doc_id);
+            phrase_positions(index, field, terms, doc_id, starts)
+        }
+        QueryNode::Or(nodes) => nodes
+            .iter()
+            .flat_map(|node| node_positions(index, field, node, doc_id))
+            .collect(),
+        QueryNode::Boost(node, _) => node_positions(index, field, node, doc_id),
+    }
+}
+
+/// Positions of `term` in `field` of one document.
+pub fn doc_positions<'a>(
+    index: &'a InvertedIndex,
+    field: Field,
+    term: &str,
+    doc_id: usize,
+) -> &'a [usize] {
+    let postings = index.postings(field, term);
+    postings
+        .binary_search_by_key(&doc_id, |(d, _)| *d)
+        .map_or(&[], |i| postings[i].1.as_slice())
+}
+
+/// Documents and start positions where all phrase terms occur at their
+/// relative offsets.
+pub fn phrase_occurrences<'a>(
+    index: &'a InvertedIndex,
+    field: Field,
+    terms: &'a [(usize, String)],
+) -> impl Iterator<Item = (usize, Vec<usize>)> + 'a {
+    index
+        .postings(field, &terms[0].1)
+        .iter()
+        .map(move |(doc_id, starts)| {
+            (
+                *doc_id,
+                phrase_positions(index, field, terms, *doc_id, starts),
+            )
+        })
+        .filter(|(_, positions)| !positions.is_empty())
 }
 
-pub fn search(index: &HashMap<String, Vec<(usize, usize)>>, query: &str) -> Vec<(usize, usize)> {
-    static EMPTY_VEC: Vec<(usize, usize)> = vec![];
-    tokenize(query)
+/// The subset of `starts` (positions of the first phrase term) at which the
+/// rest of the phrase follows.
+pub fn phrase_positions(
+    index: &InvertedIndex,
+    field: Field,
+    terms: &[(usize, String)],
+    doc_id: usize,
+    starts: &[usize],
+) -> Vec<usize> {
+    let first_offset = terms[0].0;
+    let rest: Vec<(usize, &[usize])> = terms[1..]
+        .iter()
+        .map(|(offset, term)| {
+            (
+                offset - first_offset,
+                doc_positions(index, field, term, doc_id),
+            )
+        })
+        .collect();
+    starts
         .iter()
-        .flat_map(|word| index.get(word).unwrap_or(&EMPTY_VEC))
-        .cloned()
+        .copied()
+        .filter(|pos| {
+            rest.iter()
+                .all(|(offset, positions)| positions.binary_search(&(pos + offset)).is_ok())
+        })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thesaurus::add_entry;
+
+    fn library(docs: &[(&str, &str, &str)]) -> Library {
+        let mut library = Library::new();
+        for &(name, subject, content) in docs {
+            let mut doc = Document::new();
+            doc.set_name(name.to_string());
+            doc.set_subject(subject.to_string());
+            doc.set_content(content.to_string());
+            library.mut_documents().push(doc);
+        }
+        library
+    }
+
+    fn doc_ids(results: &[ScoredDoc]) -> Vec<usize> {
+        let mut ids: Vec<usize> = results.iter().map(|result| result.doc_id).collect();
+        ids.sort();
+        ids
+    }
+
+    fn thesaurus(lines: &[&str]) -> Thesaurus {
+        let mut thesaurus = Thesaurus::new();
+        for line in lines {
+            add_entry(&mut thesaurus, line, false).unwrap();
+        }
+        thesaurus
+    }
+
+    #[test]
+    fn name_and_subject_are_indexed() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The fellowship sets out"),
+            ("Numbers 6", "Bible", "A gold ring"),
+        ]));
+        assert_eq!(
+            index.postings(Field::Subject, "rings"),
+            &[(0, vec![3])],
+            "stop words keep their positions"
+        );
+        assert_eq!(index.postings(Field::Name, "numbers"), &[(1, vec![0])]);
+        let results = search(
+            &index,
+            &parse_query("ring"),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .docs;
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0].hits,
+            vec![Hit {
+                doc_id: 1,
+                field: Field::Content,
+                pos: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn scoped_clauses_filter_the_other_clauses() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+            ("Numbers 6", "Bible", "A gold ring"),
+            ("Genesis 1", "Bible", "The heavens and the earth"),
+        ]));
+        assert_eq!(
+            doc_ids(
+                &search(
+                    &index,
+                    &parse_query("ring"),
+                    &Ranking::default(),
+                    Page::default()
+                )
+                .docs
+            ),
+            [0, 1]
+        );
+        let results = search(
+            &index,
+            &parse_query("subject:Bible ring"),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .docs;
+        assert_eq!(doc_ids(&results), [1]);
+        assert!(results[0]
+            .hits
+            .iter()
+            .all(|hit| hit.field == Field::Content));
+        assert_eq!(
+            doc_ids(
+                &search(
+                    &index,
+                    &parse_query("subject:bible"),
+                    &Ranking::default(),
+                    Page::default()
+                )
+                .docs
+            ),
+            [1, 2]
+        );
+        assert!(search(
+            &index,
+            &parse_query("name:ring"),
+            &Ranking::default(),
+            Page::default()
+        )
+        .docs
+        .is_empty());
+        assert_eq!(
+            doc_ids(
+                &search(
+                    &index,
+                    &parse_query(r#"name:"Chapter 2" ring"#),
+                    &Ranking::default(),
+                    Page::default()
+                )
+                .docs
+            ),
+            [0]
+        );
+    }
+
+    #[test]
+    fn synonyms_find_documents_without_the_query_term() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+            ("Numbers 6", "Bible", "A gold band"),
+        ]));
+        let thesaurus = thesaurus(&["ring,band"]);
+        let results = search_with_synonyms(
+            &index,
+            "ring",
+            &[],
+            &thesaurus,
+            &Ranking::default(),
+            Page::default(),
+        )
+        .1
+        .docs;
+        assert_eq!(doc_ids(&results), [0, 1]);
+        let results = search_with_synonyms(
+            &index,
+            "gold ring",
+            &[],
+            &thesaurus,
+            &Ranking::default(),
+            Page::default(),
+        )
+        .1
+        .docs;
+        assert_eq!(results[0].doc_id, 1, "each term expands on its own");
+        assert_eq!(results[0].hits.len(), 2);
+    }
+
+    #[test]
+    fn multi_word_synonyms_match_as_phrases() {
+        let index = create_inverted_index(&library(&[
+            (
+                "Chapter 2",
+                "Lord of the Rings",
+                "One ring to rule them all",
+            ),
+            ("Chapter 3", "Lord of the Rings", "Ring one"),
+        ]));
+        let results = search_with_synonyms(
+            &index,
+            "band",
+            &[],
+            &thesaurus(&["band => one ring"]),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .1
+        .docs;
+        assert_eq!(doc_ids(&results), [0]);
+    }
+
+    #[test]
+    fn exact_matches_outrank_synonym_matches() {
+        let index = create_inverted_index(&library(&[
+            ("Numbers 6", "Bible", "A gold band"),
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+        ]));
+        let results = search_with_synonyms(
+            &index,
+            "ring",
+            &[],
+            &thesaurus(&["ring,band"]),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .1
+        .docs;
+        assert_eq!(doc_ids(&results), [0, 1]);
+        assert_eq!(results[0].doc_id, 1);
+        assert!(results[0].score > results[1].score);
+    }
+
+    #[test]
+    fn rarer_terms_and_shorter_fields_score_higher() {
+        let index = create_inverted_index(&library(&[
+            ("One", "Tales", "ring gold"),
+            (
+                "Two",
+                "Tales",
+                "ring gold and a very long tale of silver and stone",
+            ),
+            ("Three", "Tales", "ring"),
+        ]));
+        let results = search(
+            &index,
+            &parse_query("gold"),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .docs;
+        assert_eq!(results[0].doc_id, 0, "the shorter field ranks first");
+        let ring = search(
+            &index,
+            &parse_query("ring"),
+            &Ranking::default(),
+            Page::default(),
+        )
+        .docs;
+        let gold = &results[0];
+        let ring_in_first = ring.iter().find(|r| r.doc_id == 0).unwrap();
+        assert!(
+            gold.score > ring_in_first.score,
+            "gold is in fewer documents"
+        );
+    }
+
+    #[test]
+    fn pages_slice_the_ranking_and_count_every_match() {
+        let index = create_inverted_index(&library(&[
+            ("One", "Tales", "ring"),
+            ("Two", "Tales", "ring ring ring"),
+            ("Three", "Tales", "ring ring"),
+            ("Four", "Tales", "gold"),
+        ]));
+        let query = parse_query("ring");
+        let ranking = Ranking::default();
+        let all = search(&index, &query, &ranking, Page::default());
+        assert_eq!(all.total_hits, 3);
+        let ranked: Vec<usize> = all.docs.iter().map(|doc| doc.doc_id).collect();
+        assert_eq!(ranked, [1, 2, 0]);
+
+        let second = search(
+            &index,
+            &query,
+            &ranking,
+            Page {
+                offset: 1,
+                limit: 1,
+            },
+        );
+        assert_eq!(second.total_hits, 3);
+        assert_eq!(second.docs.len(), 1);
+        assert_eq!(second.docs[0].doc_id, 2);
+        assert!(
+            !second.docs[0].hits.is_empty(),
+            "hits are gathered per page"
+        );
+
+        let past_the_end = search(
+            &index,
+            &query,
+            &ranking,
+            Page {
+                offset: 3,
+                limit: 10,
+            },
+        );
+        assert_eq!(past_the_end.total_hits, 3);
+        assert!(past_the_end.docs.is_empty());
+    }
+
+    fn count(value: &str, count: usize) -> FacetCount {
+        FacetCount {
+            value: value.to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn facets_count_every_match_by_subject() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+            ("Numbers 6", "Bible", "A gold ring"),
+            ("Chapter 9", "Lord of the Rings", "ring ring ring"),
+            ("Job 42", "Bible", "A ring of gold"),
+            ("Ode", "Poetry", "A ring"),
+            ("Genesis 1", "Bible", "The heavens and the earth"),
+        ]));
+        let query = parse_query("ring");
+        let page = Page {
+            offset: 0,
+            limit: 1,
+        };
+        let results = search_faceted(&index, &query, &Ranking::default(), page);
+        assert_eq!(results.total_hits, 5);
+        assert!(results.total_hits_exact);
+        assert_eq!(results.docs.len(), 1);
+        assert_eq!(
+            results.facets,
+            [Facet {
+                field: Field::Subject,
+                counts: vec![
+                    count("Bible", 2),
+                    count("Lord of the Rings", 2),
+                    count("Poetry", 1),
+                ],
+            }]
+        );
+        assert_eq!(results.facets[0].find(" bible "), Some("Bible"));
+        assert_eq!(results.facets[0].find("Law"), None);
+        assert!(search(&index, &query, &Ranking::default(), page)
+            .facets
+            .is_empty());
+    }
+
+    #[test]
+    fn filters_narrow_the_results_without_changing_scores() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+            ("Numbers 6", "Bible", "A gold ring"),
+            ("Job 42", "Bible", "A ring of gold"),
+        ]));
+        let ranking = Ranking::default();
+        let mut query = parse_query("ring");
+        let all = search(&index, &query, &ranking, Page::default());
+        query.filters.push((Field::Subject, "bible".to_string()));
+        let narrowed = search_faceted(&index, &query, &ranking, Page::default());
+        assert_eq!(narrowed.total_hits, 2);
+        assert_eq!(doc_ids(&narrowed.docs), [1, 2]);
+        for doc in &narrowed.docs {
+            let unfiltered = all.docs.iter().find(|d| d.doc_id == doc.doc_id).unwrap();
+            assert_eq!(doc.score, unfiltered.score);
+        }
+        assert_eq!(narrowed.facets[0].counts, [count("Bible", 2)]);
+    }
+
+    #[test]
+    fn numbered_pages_reject_overflowing_offsets() {
+        assert_eq!(
+            Page::numbered(3, 10),
+            Some(Page {
+                offset: 20,
+                limit: 10
+            })
+        );
+        assert_eq!(Page::numbered(0, 10), None);
+        assert_eq!(Page::numbered(3, usize::MAX / 2 + 1), None);
+        assert_eq!(
+            Page {
+                offset: usize::MAX,
+                limit: 2
+            }
+            .end(),
+            usize::MAX
+        );
+    }
+
+    #[test]
+    fn huge_pages_are_capped_at_the_library_size() {
+        let index = create_inverted_index(&library(&[
+            ("Chapter 2", "Lord of the Rings", "The One Ring"),
+            ("Numbers 6", "Bible", "A gold ring"),
+            ("Genesis 1", "Bible", "The heavens and the earth"),
+        ]));
+        let query = parse_query("ring");
+        let ranking = Ranking::default();
+        for page in [
+            Page {
+                offset: 0,
+                limit: usize::MAX,
+            },
+            Page {
+                offset: 1,
+                limit: usize::MAX,
+            },
+            Page {
+                offset: usize::MAX,
+                limit: usize::MAX,
+            },
+        ] {
+            let expected = 2usize.saturating_sub(page.offset);
+            assert_eq!(search(&index, &query, &ranking, page).docs.len(), expected);
+            assert_eq!(
+                search_exhaustive(&index, &query, &ranking, page).docs.len(),
+                expected
+            );
+            assert_eq!(
+                search_faceted(&index, &query, &ranking, page).docs.len(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn facet_counts_cover_every_match() {
+        let index = create_inverted_index(&library(&[
+            (
+                "Chapter 2",
+                "Lord of the Rings",
+                "one ring to rule them all",
+            ),
+            ("Numbers 6", "Bible", "a gold ring"),
+            ("Chapter 3", "Lord of the Rings", "ring of gold"),
+            ("Job 42", "Bible", "gold"),
+            ("Chapter 4", "Lord of the Rings", "nothing here"),
+        ]));
+        let ranking = Ranking::default();
+        for text in [
+            "ring",
+            "gold ring",
+            "\"ring of gold\"",
+            "subject:bible gold",
+            "content:gold",
+        ] {
+            let query = parse_query(text);
+            let page = Page {
+                offset: 0,
+                limit: 1,
+            };
+            let faceted = search_faceted(&index, &query, &ranking, page);
+            let exhaustive = search_exhaustive(&index, &query, &ranking, page);
+            assert_eq!(faceted.total_hits, exhaustive.total_hits, "{}", text);
+            assert!(faceted.total_hits_exact);
+            assert_eq!(faceted.docs, exhaustive.docs, "{}", text);
+            let counted: usize = faceted.facets[0].counts.iter().map(|c| c.count).sum();
+            assert_eq!(counted, exhaustive.total_hits, "{}", text);
+        }
+
+        let mut query = parse_query("gold ring");
+        query.filters.push((Field::Subject, "bible".to_string()));
+        let faceted = search_faceted(&index, &query, &ranking, Page::default());
+        assert_eq!(faceted.total_hits, 2);
+        assert_eq!(
+            faceted.facets[0].counts,
+            vec![FacetCount {
+                value: "Bible".to_string(),
+                count: 2
+            }]
+        );
+    }
+}
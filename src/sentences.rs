@@ -0,0 +1,160 @@
+use std::ops::Range;
+
+/// Abbreviations that are followed by a period but do not end a sentence,
+/// lowercased and without their final period.
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "st", "jr", "sr", "rev", "gen", "col", "lt", "sgt", "capt",
+    "mt", "vs", "cf", "e.g", "i.e", "no", "vol", "fig", "approx",
+];
+/// Quotes and brackets that may close a sentence after its final punctuation.
+const CLOSERS: &[char] = &['"', '\'', '”', '’', ')', ']'];
+/// Quotes and brackets that may open a sentence before its first word.
+const OPENERS: &[char] = &['"', '\'', '“', '‘', '(', '['];
+
+/// Splits whitespace separated `words` into sentences, returned as ranges of
+/// word indices that together cover every word.
+pub fn sentences(words: &[&str]) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    for (i, word) in words.iter().enumerate() {
+        if ends_sentence(word, words.get(i + 1).copied()) {
+            sentences.push(start..i + 1);
+            start = i + 1;
+        }
+    }
+    if start < words.len() {
+        sentences.push(start..words.len());
+    }
+    sentences
+}
+
+/// For every word index, and one past the last word, whether a sentence
+/// starts there.
+pub fn sentence_starts(words: &[&str]) -> Vec<bool> {
+    let mut starts = vec![false; words.len() + 1];
+    starts[0] = true;
+    for sentence in sentences(words) {
+        starts[sentence.end] = true;
+    }
+    starts
+}
+
+/// Whether `word` ends a sentence, given the word after it.
+///
+/// The word has to end in `.`, `!` or `?`, possibly followed by closing quotes
+/// or brackets. A period after an abbreviation (`Mr.`) or initials (`C.S.`)
+/// does not count, and neither does any punctuation followed by a lowercase
+/// word (`"Help!" she cried`). Periods inside a word, as in `3.14`, are never
+/// boundaries.
+fn ends_sentence(word: &str, next: Option<&str>) -> bool {
+    let core = word.trim_end_matches(CLOSERS);
+    if !core.ends_with(['.', '!', '?']) {
+        return false;
+    }
+    if core.ends_with('.') && !core.ends_with("..") {
+        let stem = core.trim_end_matches('.').trim_start_matches(OPENERS);
+        if is_abbreviation(stem) || is_initials(stem) {
+            return false;
+        }
+    }
+    next.is_none_or(|next| {
+        !next
+            .trim_start_matches(OPENERS)
+            .starts_with(char::is_lowercase)
+    })
+}
+
+fn is_abbreviation(stem: &str) -> bool {
+    ABBREVIATIONS.contains(&stem.to_lowercase().as_str())
+}
+
+/// `J`, `C.S` or `U.S`: single letters separated by periods.
+fn is_initials(stem: &str) -> bool {
+    !stem.is_empty()
+        && stem.split('.').all(|part| {
+            let mut chars = part.chars();
+            matches!((chars.next(), chars.next()), (Some(c), None) if c.is_alphabetic())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sentences of `text`, each joined back into a string.
+    fn split(text: &str) -> Vec<String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        sentences(&words)
+            .into_iter()
+            .map(|range| words[range].join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn sentences_end_at_final_punctuation() {
+        assert_eq!(
+            split("The ring is gold. Is it? It is! Keep it"),
+            ["The ring is gold.", "Is it?", "It is!", "Keep it"]
+        );
+        assert_eq!(split(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn abbreviations_do_not_end_sentences() {
+        assert_eq!(
+            split("Mr. Baggins met Dr. Who, e.g. at noon. Then he left."),
+            ["Mr. Baggins met Dr. Who, e.g. at noon.", "Then he left."]
+        );
+        assert_eq!(split("(Mrs. Smith) agreed."), ["(Mrs. Smith) agreed."]);
+    }
+
+    #[test]
+    fn initials_do_not_end_sentences() {
+        assert_eq!(
+            split("J. R. R. Tolkien and C.S. Lewis met at Oxford. They talked."),
+            [
+                "J. R. R. Tolkien and C.S. Lewis met at Oxford.",
+                "They talked."
+            ]
+        );
+    }
+
+    #[test]
+    fn closing_quotes_and_brackets_stay_with_the_sentence() {
+        assert_eq!(
+            split("He said \"Run.\" Then (quietly!) \"Go?\" They ran.'"),
+            [
+                "He said \"Run.\"",
+                "Then (quietly!)",
+                "\"Go?\"",
+                "They ran.'"
+            ]
+        );
+    }
+
+    #[test]
+    fn lowercase_words_continue_the_sentence() {
+        assert_eq!(
+            split("\"Help!\" she cried. \"Stop!\" (He stopped.)"),
+            ["\"Help!\" she cried.", "\"Stop!\"", "(He stopped.)"]
+        );
+    }
+
+    #[test]
+    fn periods_inside_words_are_not_boundaries() {
+        assert_eq!(
+            split("Pi is 3.14 and e is 2.718. Version 1.2.3 works... Mostly."),
+            [
+                "Pi is 3.14 and e is 2.718.",
+                "Version 1.2.3 works...",
+                "Mostly."
+            ]
+        );
+    }
+
+    #[test]
+    fn starts_mark_every_sentence_and_the_end() {
+        let words: Vec<&str> = "One. Two three. Four".split_whitespace().collect();
+        assert_eq!(sentence_starts(&words), [true, true, false, true, true]);
+    }
+}
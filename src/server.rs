@@ -0,0 +1,485 @@
+use std::collections::HashSet;
+use std::io::{self, Read};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use librarian::{Index, Library};
+
+use crate::batch::BatchQuery;
+use crate::cli::Options;
+
+/// Requests handled at the same time. Searches and document reads run in
+/// parallel; adding and deleting documents run one at a time, and searches go
+/// on against the old index while the new one is built.
+const WORKERS: usize = 4;
+
+/// Largest request body accepted, in bytes.
+const MAX_BODY: u64 = 10 * 1024 * 1024;
+
+/// The library being served and its index, replaced after every change.
+struct State {
+    library: Library,
+    index: Index,
+    /// Ids of deleted documents, which stay reserved.
+    deleted: HashSet<usize>,
+}
+
+/// What the workers share: the current state, and a lock held by a change
+/// from reading the state until it replaces it, so changes do not overwrite
+/// each other.
+struct Shared {
+    state: RwLock<State>,
+    changes: Mutex<()>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewDocument {
+    name: String,
+    subject: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NewDocuments {
+    One(NewDocument),
+    Many(Vec<NewDocument>),
+}
+
+#[derive(Serialize)]
+struct DocumentJson<'a> {
+    id: usize,
+    name: &'a str,
+    subject: &'a str,
+    content: &'a str,
+}
+
+/// A failed request, answered as `{"error": "..."}`.
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> HttpError {
+        HttpError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+/// A status and a JSON body, if any.
+type Reply = Result<(u16, Option<Value>), HttpError>;
+
+/// Serves `library` on `port` of the loopback interface until killed; port 0
+/// picks a free one. Changes are kept in memory only. The endpoints are:
+///
+/// - `GET /search?q=ring&subject=Bible&limit=5`: results as with `--format
+///   json`; also takes `page`, `offset`, `max_snippets`, `sentences` and a
+///   value for any facet field
+/// - `GET /documents/{id}`: a document with its `id`, `name`, `subject` and
+///   `content`
+/// - `POST /documents`: adds one document or an array of them, given as
+///   `{"name", "subject", "content"}`, and returns their `ids`
+/// - `DELETE /documents/{id}`: removes a document; ids are never reused
+pub fn run(library: Library, index: Index, port: u16, options: &Options) -> io::Result<()> {
+    let server = Server::http(("127.0.0.1", port)).map_err(io::Error::other)?;
+    if let Some(addr) = server.server_addr().to_ip() {
+        println!("Listening on http://{}", addr);
+    }
+    serve(&server, library, index, options);
+    Ok(())
+}
+
+/// Answers the requests to `server` until it is closed.
+fn serve(server: &Server, library: Library, index: Index, options: &Options) {
+    let shared = Shared {
+        state: RwLock::new(State {
+            library,
+            index,
+            deleted: HashSet::new(),
+        }),
+        changes: Mutex::new(()),
+    };
+    thread::scope(|scope| {
+        for _ in 0..WORKERS {
+            scope.spawn(|| work(server, &shared, options));
+        }
+    });
+}
+
+/// Answers requests on one thread until the server is closed.
+fn work(server: &Server, shared: &Shared, options: &Options) {
+    while let Ok(mut request) = server.recv() {
+        let reply = handle(&mut request, shared, options);
+        let (status, body) = match reply {
+            Ok(reply) => reply,
+            Err(err) => (err.status, Some(json!({ "error": err.message }))),
+        };
+        let response = match body {
+            Some(body) => Response::from_string(body.to_string())
+                .with_header(json_header())
+                .with_status_code(status),
+            None => Response::from_string("").with_status_code(status),
+        };
+        if let Err(err) = request.respond(response) {
+            eprintln!("Error: cannot answer request: {}", err);
+        }
+    }
+}
+
+fn handle(request: &mut Request, shared: &Shared, options: &Options) -> Reply {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["search"]) => search(&shared.state, query, options),
+        (Method::Get, ["documents", id]) => get_document(&shared.state, parse_id(id)?),
+        (Method::Post, ["documents"]) => {
+            let body = read_body(request)?;
+            add_documents(shared, &body)
+        }
+        (Method::Delete, ["documents", id]) => delete_document(shared, parse_id(id)?),
+        (_, ["search"]) | (_, ["documents"]) | (_, ["documents", _]) => {
+            Err(HttpError::new(405, "method not allowed"))
+        }
+        _ => Err(HttpError::new(404, format!("no such endpoint `{}`", path))),
+    }
+}
+
+fn search(state: &RwLock<State>, query: &str, options: &Options) -> Reply {
+    let batch_query = search_params(query).map_err(|err| HttpError::new(400, err))?;
+    let query = batch_query
+        .to_query(options)
+        .map_err(|err| HttpError::new(400, err))?;
+    let state = state.read().unwrap();
+    let results = state.index.search(&state.library, &query);
+    Ok((200, Some(serde_json::to_value(&results).unwrap())))
+}
+
+fn get_document(state: &RwLock<State>, id: usize) -> Reply {
+    let state = state.read().unwrap();
+    let doc = state
+        .library
+        .get(id)
+        .filter(|_| !state.deleted.contains(&id))
+        .ok_or_else(|| HttpError::new(404, format!("no document {}", id)))?;
+    let json = DocumentJson {
+        id,
+        name: &doc.name,
+        subject: &doc.subject,
+        content: &doc.content,
+    };
+    Ok((200, Some(serde_json::to_value(&json).unwrap())))
+}
+
+fn add_documents(shared: &Shared, body: &[u8]) -> Reply {
+    let docs = match serde_json::from_slice(body) {
+        Ok(NewDocuments::One(doc)) => vec![doc],
+        Ok(NewDocuments::Many(docs)) => docs,
+        Err(_) => {
+            return Err(HttpError::new(
+                400,
+                "expected a document or an array of documents with a name, subject and content",
+            ))
+        }
+    };
+    let ids = change(shared, |library, _| {
+        Ok(docs
+            .iter()
+            .map(|doc| library.add(&doc.name, &doc.subject, &doc.content))
+            .collect::<Vec<usize>>())
+    })?;
+    Ok((201, Some(json!({ "ids": ids }))))
+}
+
+fn delete_document(shared: &Shared, id: usize) -> Reply {
+    change(shared, |library, deleted| {
+        if deleted.contains(&id) || library.remove(id).is_none() {
+            return Err(HttpError::new(404, format!("no document {}", id)));
+        }
+        deleted.insert(id);
+        Ok(())
+    })?;
+    Ok((204, None))
+}
+
+/// Applies `edit` to a copy of the library and the deleted ids, then swaps in
+/// the copy with a new index. Readers only wait for the swap, not for the
+/// index to be built.
+fn change<T>(
+    shared: &Shared,
+    edit: impl FnOnce(&mut Library, &mut HashSet<usize>) -> Result<T, HttpError>,
+) -> Result<T, HttpError> {
+    let _changing = shared.changes.lock().unwrap();
+    let (value, state) = {
+        let current = shared.state.read().unwrap();
+        let mut library = current.library.clone();
+        let mut deleted = current.deleted.clone();
+        let value = edit(&mut library, &mut deleted)?;
+        let index = current.index.rebuild(&library);
+        (
+            value,
+            State {
+                library,
+                index,
+                deleted,
+            },
+        )
+    };
+    *shared.state.write().unwrap() = state;
+    Ok(value)
+}
+
+/// Reads the query string of a search: `q` is the query text and parameters
+/// not naming an option are facet filters.
+fn search_params(query: &str) -> Result<BatchQuery, String> {
+    let mut batch_query = BatchQuery::default();
+    let mut text = None;
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let (name, value) = (percent_decode(name)?, percent_decode(value)?);
+        let count = || {
+            value
+                .parse()
+                .map_err(|_| format!("{} expects a number, got `{}`", name, value))
+        };
+        match name.as_str() {
+            "q" => text = Some(value.clone()),
+            "limit" => batch_query.limit = Some(count()?),
+            "page" => batch_query.page = Some(count()?),
+            "offset" => batch_query.offset = Some(count()?),
+            "max_snippets" => batch_query.max_snippets = Some(count()?),
+            "sentences" => {
+                let sentences = value
+                    .parse()
+                    .map_err(|_| format!("sentences expects true or false, got `{}`", value))?;
+                batch_query.sentences = Some(sentences);
+            }
+            _ => {
+                batch_query.filters.insert(name, value);
+            }
+        }
+    }
+    batch_query.query = text.ok_or("missing query parameter `q`")?;
+    Ok(batch_query)
+}
+
+/// Decodes `%XX` escapes and `+` for a space, as in query strings.
+fn percent_decode(text: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let byte = text
+                    .get(i + 1..i + 3)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or_else(|| format!("invalid escape in `{}`", text))?;
+                decoded.push(byte);
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8(decoded).map_err(|_| format!("`{}` is not UTF-8", text))
+}
+
+fn parse_id(id: &str) -> Result<usize, HttpError> {
+    id.parse()
+        .map_err(|_| HttpError::new(404, format!("no document `{}`", id)))
+}
+
+fn read_body(request: &mut Request) -> Result<Vec<u8>, HttpError> {
+    if request
+        .body_length()
+        .is_some_and(|length| length as u64 > MAX_BODY)
+    {
+        return Err(HttpError::new(413, "request body too large"));
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body)
+        .map_err(|err| HttpError::new(400, err.to_string()))?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(HttpError::new(413, "request body too large"));
+    }
+    Ok(body)
+}
+
+fn json_header() -> Header {
+    Header::from_bytes("Content-Type", "application/json").unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpStream};
+    use std::time::Duration;
+
+    use super::*;
+    use crate::cli::parse_args;
+    use crate::testing::library;
+
+    /// Serves the test library on a free port of the loopback interface for
+    /// the rest of the test run.
+    fn start() -> SocketAddr {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+        thread::spawn(move || {
+            let library = library();
+            let index = Index::new(&library);
+            let options = parse_args(["serve".to_string()]).unwrap();
+            serve(&server, library, index, &options);
+        });
+        addr
+    }
+
+    /// Sends one request and returns the status and the JSON body, if any.
+    fn send(addr: SocketAddr, method: &str, path: &str, headers: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}\r\n{}",
+            method, path, headers, body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(body).unwrap()
+        };
+        (status, body)
+    }
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let length = format!("Content-Length: {}\r\n", body.len());
+        send(addr, method, path, &length, body)
+    }
+
+    fn total_hits(addr: SocketAddr, query: &str) -> u64 {
+        let (status, body) = request(addr, "GET", &format!("/search?{}", query), "");
+        assert_eq!(status, 200, "{}", body);
+        body["total_hits"].as_u64().unwrap()
+    }
+
+    #[test]
+    fn query_strings_are_decoded() {
+        assert_eq!(percent_decode("one+ring%21").unwrap(), "one ring!");
+        assert_eq!(percent_decode("caf%C3%A9").unwrap(), "café");
+        assert!(percent_decode("100%").is_err());
+        assert!(percent_decode("%zz").is_err());
+        assert!(percent_decode("%ff").is_err());
+
+        let params = search_params("q=gold+ring&subject=Bible&limit=5&sentences=true").unwrap();
+        assert_eq!(params.query, "gold ring");
+        assert_eq!(params.limit, Some(5));
+        assert_eq!(params.sentences, Some(true));
+        assert_eq!(params.filters["subject"], "Bible");
+        assert!(search_params("limit=5").is_err());
+        assert!(search_params("q=ring&limit=five").is_err());
+        assert!(search_params("q=ring&sentences=maybe").is_err());
+    }
+
+    #[test]
+    fn searches_and_reads_documents() {
+        let addr = start();
+        let (status, body) = request(addr, "GET", "/search?q=ring&subject=Bible&limit=5", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["total_hits"], 2);
+        assert_eq!(body["facets"][0]["counts"][0]["value"], "Bible");
+
+        let (status, body) = request(addr, "GET", "/documents/0", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["content"], "The One Ring, and another ring");
+    }
+
+    #[test]
+    fn adds_and_deletes_documents() {
+        let addr = start();
+        let new = r#"[{"name": "Chapter 9", "subject": "Hobbit", "content": "A dragon ring"},
+            {"name": "Chapter 10", "subject": "Hobbit", "content": "Smaug"}]"#;
+        let (status, body) = request(addr, "POST", "/documents", new);
+        assert_eq!(status, 201);
+        assert_eq!(body["ids"], json!([3, 4]));
+        assert_eq!(total_hits(addr, "q=ring"), 4);
+
+        assert_eq!(request(addr, "DELETE", "/documents/0", "").0, 204);
+        assert_eq!(total_hits(addr, "q=ring"), 3);
+        assert_eq!(request(addr, "GET", "/documents/0", "").0, 404);
+        assert_eq!(request(addr, "DELETE", "/documents/0", "").0, 404);
+
+        let (_, body) = request(addr, "POST", "/documents", new);
+        assert_eq!(body["ids"], json!([5, 6]), "ids are not reused");
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let addr = start();
+        for query in [
+            "q=ring&page=4611686018427387904&limit=8",
+            "q=ring&limit=0",
+            "q=ring&page=1&offset=2",
+            "q=ring&limit=many",
+            "q=ring&color=red",
+            "limit=5",
+        ] {
+            let (status, body) = request(addr, "GET", &format!("/search?{}", query), "");
+            assert_eq!(status, 400, "{}", query);
+            assert!(body["error"].is_string(), "{}", body);
+        }
+        assert_eq!(total_hits(addr, "q=ring&limit=1000000000000000000"), 3);
+        assert_eq!(total_hits(addr, "q=ring&offset=18446744073709551615"), 3);
+
+        assert_eq!(request(addr, "GET", "/documents/99", "").0, 404);
+        assert_eq!(request(addr, "GET", "/documents/one", "").0, 404);
+        assert_eq!(request(addr, "GET", "/nowhere", "").0, 404);
+        assert_eq!(request(addr, "PUT", "/documents/0", "").0, 405);
+        assert_eq!(request(addr, "POST", "/search", "").0, 405);
+        assert_eq!(request(addr, "POST", "/documents", "{}").0, 400);
+
+        let too_long = format!("Content-Length: {}\r\n", MAX_BODY + 1);
+        assert_eq!(send(addr, "POST", "/documents", &too_long, "").0, 413);
+        // Still answering after all of that
+        assert_eq!(total_hits(addr, "q=ring"), 3);
+    }
+
+    #[test]
+    fn searches_see_whole_changes_while_documents_are_added() {
+        let addr = start();
+        let writer = thread::spawn(move || {
+            for i in 0..20 {
+                let doc =
+                    json!({"name": format!("Ring {}", i), "subject": "Rings", "content": "ring"});
+                assert_eq!(request(addr, "POST", "/documents", &doc.to_string()).0, 201);
+            }
+        });
+        let mut last = 3;
+        while !writer.is_finished() {
+            let hits = total_hits(addr, "q=ring");
+            assert!((last..=23).contains(&hits), "{} after {}", hits, last);
+            last = hits;
+        }
+        writer.join().unwrap();
+        assert_eq!(total_hits(addr, "q=ring"), 23);
+    }
+}
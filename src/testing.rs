@@ -0,0 +1,16 @@
+//! Fixtures shared by the tests of the commands.
+
+use librarian::Library;
+
+/// Three short documents mentioning rings, two of them in the Bible.
+pub fn library() -> Library {
+    let mut library = Library::new();
+    library.add(
+        "Chapter 2",
+        "Lord of the Rings",
+        "The One Ring, and another ring",
+    );
+    library.add("Numbers 6", "Bible", "A gold ring");
+    library.add("Job 42", "Bible", "A ring of gold");
+    library
+}
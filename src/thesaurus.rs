@@ -0,0 +1,606 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+use crate::query::{analyze_node, Clause, Query, QueryNode};
+use crate::utils::tokenize;
+
+const THESAURUS_FILE: &str = "thesaurus.txt";
+
+/// Weight applied to matches of an expanded synonym, relative to 1.0 for the
+/// term the user actually typed.
+pub const SYNONYM_WEIGHT: f32 = 0.5;
+
+/// Normalized headword -> synonyms, as written in the thesaurus.
+pub type Thesaurus = HashMap<String, Vec<String>>;
+
+pub fn fetch_synonyms<'a>(word: &str, thesaurus: &'a Thesaurus) -> &'a [String] {
+    thesaurus
+        .get(word)
+        .map_or(&[], |synonyms| synonyms.as_slice())
+}
+
+/// Normalizes a thesaurus headword or query phrase into a lookup key: its
+/// analyzed tokens joined by single spaces.
+pub fn thesaurus_key(text: &str) -> String {
+    tokenize(text)
+        .into_iter()
+        .map(|(_, term)| term)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Rewrites every term and quoted phrase of `query` into an OR-group of itself
+/// and its synonyms, looking each one up in the thesaurus independently.
+/// Synonyms are boosted by `SYNONYM_WEIGHT` so exact matches rank first.
+///
+/// Runs of adjacent bare words that form a multi-word headword (`look for`) are
+/// first merged into a phrase, so they expand as a unit. Multi-word synonyms are
+/// expanded into phrases rather than independent terms.
+pub fn expand_synonyms(query: &Query, thesaurus: &Thesaurus) -> Query {
+    let max_words = thesaurus
+        .keys()
+        .map(|key| key.split(' ').count())
+        .max()
+        .unwrap_or(1);
+
+    let mut clauses = Vec::new();
+    let mut i = 0;
+    while i < query.clauses.len() {
+        let run = headword_run(&query.clauses[i..], max_words, thesaurus);
+        let clause = &query.clauses[i];
+        let node = if run > 1 {
+            let phrase = query.clauses[i..i + run]
+                .iter()
+                .map(|c| match &c.node {
+                    QueryNode::Term(term) => (c.pos - clause.pos, term.clone()),
+                    _ => unreachable!("headword runs only contain terms"),
+                })
+                .collect();
+            QueryNode::Phrase(phrase)
+        } else {
+            clause.node.clone()
+        };
+
+        clauses.push(Clause {
+            field: clause.field,
+            node: expand_node(&node, thesaurus),
+            pos: clause.pos,
+        });
+        i += run;
+    }
+    Query {
+        clauses,
+        filters: query.filters.clone(),
+    }
+}
+
+/// Length of the longest run of bare terms at the start of `clauses`, all in
+/// the same field, whose joined text is a thesaurus headword. Returns 1 if none.
+fn headword_run(clauses: &[Clause], max_words: usize, thesaurus: &Thesaurus) -> usize {
+    let field = clauses[0].field;
+    let terms: Vec<&str> = clauses
+        .iter()
+        .take(max_words)
+        .map_while(|clause| match &clause.node {
+            QueryNode::Term(term) if clause.field == field => Some(term.as_str()),
+            _ => None,
+        })
+        .collect();
+
+    (2..=terms.len())
+        .rev()
+        .find(|&len| thesaurus.contains_key(&terms[..len].join(" ")))
+        .unwrap_or(1)
+}
+
+fn expand_node(node: &QueryNode, thesaurus: &Thesaurus) -> QueryNode {
+    let key = match node {
+        QueryNode::Term(term) => term.clone(),
+        QueryNode::Phrase(terms) => terms
+            .iter()
+            .map(|(_, term)| term.as_str())
+            .collect::<Vec<_>>()
+            .join(" "),
+        QueryNode::Or(_) | QueryNode::Boost(..) => return node.clone(),
+    };
+
+    let mut alternatives = vec![node.clone()];
+    for synonym in fetch_synonyms(&key, thesaurus) {
+        if let Some(alternative) = analyze_node(synonym) {
+            let alternative = QueryNode::Boost(Box::new(alternative), SYNONYM_WEIGHT);
+            if !alternatives.contains(&alternative) {
+                alternatives.push(alternative);
+            }
+        }
+    }
+
+    if alternatives.len() == 1 {
+        node.clone()
+    } else {
+        QueryNode::Or(alternatives)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThesaurusFormat {
+    /// `thesaurus.txt`: comma separated synonym sets and `=>` rules.
+    Native,
+    /// Solr `synonyms.txt`: like native, plus backslash escapes (`\,`, `\=>`).
+    Solr,
+    /// WordNet's Prolog `wn_s.pl`; words sharing a synset id form a set.
+    WordNet,
+}
+
+impl ThesaurusFormat {
+    pub fn from_name(name: &str) -> Option<ThesaurusFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "native" => Some(ThesaurusFormat::Native),
+            "solr" => Some(ThesaurusFormat::Solr),
+            "wordnet" => Some(ThesaurusFormat::WordNet),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ThesaurusError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ThesaurusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ThesaurusError::Io(err) => write!(f, "could not read thesaurus: {}", err),
+            ThesaurusError::Parse { line, message } => {
+                write!(f, "thesaurus line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ThesaurusError {}
+
+impl From<io::Error> for ThesaurusError {
+    fn from(err: io::Error) -> Self {
+        ThesaurusError::Io(err)
+    }
+}
+
+/// Loads `thesaurus.txt` from the working directory, or an empty thesaurus if
+/// there is none.
+pub fn load_thesaurus() -> Result<Thesaurus, ThesaurusError> {
+    load_thesaurus_from(Path::new(THESAURUS_FILE), ThesaurusFormat::Native, true)
+}
+
+/// Loads a thesaurus file in the given format. With `optional` set a missing
+/// file yields an empty thesaurus instead of an error.
+pub fn load_thesaurus_from(
+    path: &Path,
+    format: ThesaurusFormat,
+    optional: bool,
+) -> Result<Thesaurus, ThesaurusError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if optional && err.kind() == io::ErrorKind::NotFound => {
+            return Ok(Thesaurus::new())
+        }
+        Err(err) => return Err(err.into()),
+    };
+    parse_thesaurus(io::BufReader::new(file), format)
+}
+
+/// Parses a thesaurus from `reader`. Blank lines and lines starting with `#`
+/// are skipped; errors report the 1-based line number.
+pub fn parse_thesaurus(
+    reader: impl BufRead,
+    format: ThesaurusFormat,
+) -> Result<Thesaurus, ThesaurusError> {
+    let mut thesaurus = HashMap::new();
+    // Ordered by synset id, so a word in several synsets always expands to
+    // their words in the same order
+    let mut synsets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = match format {
+            ThesaurusFormat::Native => add_entry(&mut thesaurus, line, false),
+            ThesaurusFormat::Solr => add_entry(&mut thesaurus, line, true),
+            ThesaurusFormat::WordNet => parse_wordnet_line(line).map(|(synset, word)| {
+                synsets.entry(synset).or_default().push(word);
+            }),
+        };
+        result.map_err(|message| ThesaurusError::Parse {
+            line: i + 1,
+            message,
+        })?;
+    }
+
+    for set in synsets.values() {
+        for word in set {
+            add_synonyms(&mut thesaurus, word, set);
+        }
+    }
+    Ok(thesaurus)
+}
+
+/// Adds one thesaurus line.
+///
+/// A plain comma separated list (`document,doc,file,paper`) is a synonym set:
+/// every word expands to all the others. A rule with `=>` (`i-pod, i pod => ipod`)
+/// is one-way: the words on the left expand to the words on the right only.
+/// With `escapes` set, `\` makes the next character literal, as in Solr.
+pub fn add_entry(thesaurus: &mut Thesaurus, line: &str, escapes: bool) -> Result<(), String> {
+    let sides = split_unescaped(line, "=>", escapes);
+    match sides.as_slice() {
+        [set] => {
+            let set = split_words(set, escapes);
+            for word in &set {
+                add_synonyms(thesaurus, word, &set);
+            }
+        }
+        [from, to] => {
+            let (from, to) = (split_words(from, escapes), split_words(to, escapes));
+            if from.is_empty() || to.is_empty() {
+                return Err("`=>` rule needs words on both sides".to_string());
+            }
+            for word in &from {
+                add_synonyms(thesaurus, word, &to);
+            }
+        }
+        _ => return Err("more than one `=>` in rule".to_string()),
+    }
+    Ok(())
+}
+
+/// Splits a comma separated list into trimmed, non-empty words.
+fn split_words(list: &str, escapes: bool) -> Vec<String> {
+    split_unescaped(list, ",", escapes)
+        .into_iter()
+        .map(|word| word.trim().to_string())
+        .filter(|word| !word.is_empty())
+        .map(|word| if escapes { unescape(&word) } else { word })
+        .collect()
+}
+
+/// Splits `text` on `separator`, skipping separators preceded by a backslash if
+/// `escapes` is set. Escapes are left in place for `unescape`.
+fn split_unescaped(text: &str, separator: &str, escapes: bool) -> Vec<String> {
+    if !escapes {
+        return text.split(separator).map(|part| part.to_string()).collect();
+    }
+
+    let mut parts = vec![String::new()];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix(separator) {
+            parts.push(String::new());
+            rest = after;
+            continue;
+        }
+        let len = match rest[c.len_utf8()..].chars().next() {
+            Some(escaped) if c == '\\' => 1 + escaped.len_utf8(),
+            _ => c.len_utf8(),
+        };
+        parts.last_mut().unwrap().push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    parts
+}
+
+fn unescape(word: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Parses a WordNet `wn_s.pl` fact such as `s(100001740,1,'entity',n,1,11).`
+/// into its synset id and word.
+fn parse_wordnet_line(line: &str) -> Result<(String, String), String> {
+    let malformed = || format!("expected `s(synset_id,w_num,'word',...).`, got `{}`", line);
+    let args = line
+        .strip_prefix("s(")
+        .and_then(|rest| rest.strip_suffix(")."))
+        .ok_or_else(malformed)?;
+
+    let (synset, rest) = args.split_once(',').ok_or_else(malformed)?;
+    let (_, rest) = rest.split_once(',').ok_or_else(malformed)?;
+    let quoted = rest.strip_prefix('\'').ok_or_else(malformed)?;
+
+    // Quotes inside the word are doubled, e.g. 'jack-o''-lantern'
+    let mut word = String::new();
+    let mut chars = quoted.chars().peekable();
+    loop {
+        match chars.next() {
+            Some('\'') if chars.peek() == Some(&'\'') => {
+                chars.next();
+                word.push('\'');
+            }
+            Some('\'') => break,
+            Some(c) => word.push(c),
+            None => return Err(malformed()),
+        }
+    }
+    Ok((synset.trim().to_string(), word.replace('_', " ")))
+}
+
+fn add_synonyms(thesaurus: &mut Thesaurus, word: &str, synonyms: &[String]) {
+    let key = thesaurus_key(word);
+    if key.is_empty() {
+        return;
+    }
+    let entry = thesaurus.entry(key.clone()).or_default();
+    for synonym in synonyms {
+        let synonym_key = thesaurus_key(synonym);
+        if synonym_key.is_empty() || synonym_key == key {
+            continue;
+        }
+        if !entry
+            .iter()
+            .any(|existing| thesaurus_key(existing) == synonym_key)
+        {
+            entry.push(synonym.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query;
+
+    fn thesaurus(lines: &[&str]) -> Thesaurus {
+        let mut thesaurus = Thesaurus::new();
+        for line in lines {
+            add_entry(&mut thesaurus, line, false).unwrap();
+        }
+        thesaurus
+    }
+
+    fn term(term: &str) -> QueryNode {
+        QueryNode::Term(term.to_string())
+    }
+
+    fn phrase(terms: &[(usize, &str)]) -> QueryNode {
+        QueryNode::Phrase(terms.iter().map(|&(pos, t)| (pos, t.to_string())).collect())
+    }
+
+    fn synonym(node: QueryNode) -> QueryNode {
+        QueryNode::Boost(Box::new(node), SYNONYM_WEIGHT)
+    }
+
+    fn expand(text: &str, lines: &[&str]) -> Vec<QueryNode> {
+        let query = expand_synonyms(&parse_query(text), &thesaurus(lines));
+        query
+            .clauses
+            .into_iter()
+            .map(|clause| clause.node)
+            .collect()
+    }
+
+    #[test]
+    fn every_term_gets_its_own_or_group() {
+        assert_eq!(
+            expand("gold ring staff", &["gold,golden", "ring,band,hoop"]),
+            [
+                QueryNode::Or(vec![term("gold"), synonym(term("golden"))]),
+                QueryNode::Or(vec![
+                    term("ring"),
+                    synonym(term("band")),
+                    synonym(term("hoop"))
+                ]),
+                term("staff"),
+            ]
+        );
+    }
+
+    #[test]
+    fn scoped_terms_keep_their_field() {
+        let query = expand_synonyms(&parse_query("subject:ring"), &thesaurus(&["ring,band"]));
+        assert_eq!(query.clauses[0].field, Some(crate::search::Field::Subject));
+        assert_eq!(
+            query.clauses[0].node,
+            QueryNode::Or(vec![term("ring"), synonym(term("band"))])
+        );
+    }
+
+    #[test]
+    fn multi_word_headwords_span_stop_words() {
+        let nodes = expand("the lord of the rings film", &["Lord of the Rings, LOTR"]);
+        assert_eq!(
+            nodes,
+            [
+                QueryNode::Or(vec![
+                    phrase(&[(0, "lord"), (3, "rings")]),
+                    synonym(term("lotr"))
+                ]),
+                term("film"),
+            ]
+        );
+    }
+
+    #[test]
+    fn headwords_do_not_span_fields() {
+        let nodes = expand("name:look for", &["look for, search"]);
+        assert_eq!(nodes, [term("look"), term("for")]);
+    }
+
+    #[test]
+    fn multi_word_synonyms_become_phrases() {
+        assert_eq!(
+            expand("ring", &["ring => one ring, band"]),
+            [QueryNode::Or(vec![
+                term("ring"),
+                synonym(phrase(&[(0, "one"), (1, "ring")])),
+                synonym(term("band"))
+            ])]
+        );
+    }
+
+    #[test]
+    fn sets_expand_every_word_to_the_others() {
+        let thesaurus = thesaurus(&["document,doc,file"]);
+        assert_eq!(fetch_synonyms("doc", &thesaurus), ["document", "file"]);
+        assert_eq!(fetch_synonyms("file", &thesaurus), ["document", "doc"]);
+    }
+
+    #[test]
+    fn rules_do_not_expand_in_reverse() {
+        let lines = ["i-pod, i pod => ipod"];
+        assert_eq!(
+            expand("i-pod", &lines),
+            [QueryNode::Or(vec![term("i-pod"), synonym(term("ipod"))])]
+        );
+        assert_eq!(
+            expand("i pod", &lines),
+            [QueryNode::Or(vec![
+                phrase(&[(0, "i"), (1, "pod")]),
+                synonym(term("ipod"))
+            ])]
+        );
+        assert_eq!(expand("ipod", &lines), [term("ipod")]);
+    }
+
+    #[test]
+    fn synonyms_are_weighted_below_typed_terms() {
+        let nodes = expand("ring", &["ring,band"]);
+        let QueryNode::Or(alternatives) = &nodes[0] else {
+            panic!("not expanded: {:?}", nodes);
+        };
+        assert_eq!(alternatives[0], term("ring"));
+        assert_eq!(
+            alternatives[1],
+            QueryNode::Boost(Box::new(term("band")), SYNONYM_WEIGHT)
+        );
+        assert_eq!(SYNONYM_WEIGHT, 0.5);
+    }
+
+    fn parse(text: &str, format: ThesaurusFormat) -> Result<Thesaurus, ThesaurusError> {
+        parse_thesaurus(text.as_bytes(), format)
+    }
+
+    fn parse_error_line(text: &str, format: ThesaurusFormat) -> usize {
+        match parse(text, format) {
+            Err(ThesaurusError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn native_sets_expand_every_word_to_the_others() {
+        let thesaurus = parse(
+            "# comment\n\n document , doc,file \n",
+            ThesaurusFormat::Native,
+        )
+        .unwrap();
+        assert_eq!(thesaurus["document"], ["doc", "file"]);
+        assert_eq!(thesaurus["doc"], ["document", "file"]);
+        assert_eq!(thesaurus["file"], ["document", "doc"]);
+        assert_eq!(thesaurus.len(), 3);
+    }
+
+    #[test]
+    fn native_rules_are_one_way() {
+        let thesaurus = parse("i-pod, i pod => ipod\n", ThesaurusFormat::Native).unwrap();
+        assert_eq!(thesaurus["i-pod"], ["ipod"]);
+        assert_eq!(thesaurus["i pod"], ["ipod"]);
+        assert!(!thesaurus.contains_key("ipod"));
+    }
+
+    #[test]
+    fn native_keeps_backslashes() {
+        let thesaurus = parse("a\\,b, c\n", ThesaurusFormat::Native).unwrap();
+        assert_eq!(thesaurus["c"], ["a\\", "b"]);
+    }
+
+    #[test]
+    fn errors_report_the_line_number() {
+        let text = "# comment\n\na, b\nx => y => z\n";
+        assert_eq!(parse_error_line(text, ThesaurusFormat::Native), 4);
+        assert_eq!(
+            parse_error_line("a, b\n => y\n", ThesaurusFormat::Native),
+            2
+        );
+        assert_eq!(parse_error_line("x =>\n", ThesaurusFormat::Solr), 1);
+    }
+
+    #[test]
+    fn solr_escapes_separators() {
+        let thesaurus = parse("1\\,000, thousand\nx \\=> y, z\n", ThesaurusFormat::Solr).unwrap();
+        assert_eq!(thesaurus["thousand"], ["1,000"]);
+        assert_eq!(thesaurus["z"], ["x => y"]);
+    }
+
+    #[test]
+    fn solr_rules_are_one_way() {
+        let thesaurus = parse("tv, telly => television\n", ThesaurusFormat::Solr).unwrap();
+        assert_eq!(thesaurus["telly"], ["television"]);
+        assert!(!thesaurus.contains_key("television"));
+    }
+
+    #[test]
+    fn wordnet_groups_words_by_synset() {
+        let text = "\
+s(100001740,1,'lantern',n,1,0).
+s(100001740,2,'jack-o''-lantern',n,1,0).
+s(107609840,1,'ice_cream',n,1,11).
+s(107609840,2,'icecream',n,1,0).
+";
+        let thesaurus = parse(text, ThesaurusFormat::WordNet).unwrap();
+        assert_eq!(thesaurus["lantern"], ["jack-o'-lantern"]);
+        assert_eq!(thesaurus["jack-o'-lantern"], ["lantern"]);
+        assert_eq!(thesaurus["ice cream"], ["icecream"]);
+    }
+
+    #[test]
+    fn wordnet_expands_words_in_synset_order() {
+        let text = "\
+s(108420278,1,'bank',n,2,883).
+s(108420278,2,'depository_financial_institution',n,1,0).
+s(109213565,1,'bank',n,1,99).
+s(109213565,2,'slope',n,1,0).
+s(100169305,1,'bank',n,3,0).
+s(100169305,2,'banking',n,1,0).
+";
+        for _ in 0..5 {
+            let thesaurus = parse(text, ThesaurusFormat::WordNet).unwrap();
+            assert_eq!(
+                thesaurus["bank"],
+                ["banking", "depository financial institution", "slope"]
+            );
+        }
+    }
+
+    #[test]
+    fn wordnet_rejects_malformed_facts() {
+        let text = "s(100001740,1,'entity',n,1,11).\ng(100001740,'a gloss').\n";
+        assert_eq!(parse_error_line(text, ThesaurusFormat::WordNet), 2);
+        let unterminated = "s(100001740,1,'entity,n,1,11).\n";
+        assert_eq!(parse_error_line(unterminated, ThesaurusFormat::WordNet), 1);
+    }
+
+    #[test]
+    fn missing_files_are_errors_unless_optional() {
+        let path = std::env::temp_dir().join("librarian-no-such-thesaurus.txt");
+        let thesaurus = load_thesaurus_from(&path, ThesaurusFormat::Native, true).unwrap();
+        assert!(thesaurus.is_empty());
+        assert!(matches!(
+            load_thesaurus_from(&path, ThesaurusFormat::Native, false),
+            Err(ThesaurusError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,549 @@
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::cli::Options;
+use crate::pager::{match_style, DocumentView};
+use librarian::highlighter::Highlighter;
+use librarian::render::{sanitize_line, Highlight};
+use librarian::{Field, Index, Library, Page, Query, SearchResults, SnippetOptions};
+
+const KEYS: &str =
+    "Tab: next pane  Enter: search/open/filter  PgUp/PgDn: page  n/p: next/previous hit  Esc: quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Query,
+    Results,
+    Document,
+    Facets,
+}
+
+impl Focus {
+    const ORDER: [Focus; 4] = [Focus::Query, Focus::Results, Focus::Document, Focus::Facets];
+
+    fn step(self, forward: bool) -> Focus {
+        let i = Focus::ORDER.iter().position(|&f| f == self).unwrap_or(0);
+        let n = Focus::ORDER.len();
+        let next = if forward { i + 1 } else { i + n - 1 };
+        Focus::ORDER[next % n]
+    }
+}
+
+/// The full-screen browser: a query box, ranked results with snippets, the
+/// selected document and the subjects of the results.
+pub struct App<'a> {
+    library: &'a Library,
+    index: &'a Index,
+    page: Page,
+    snippets: SnippetOptions,
+    focus: Focus,
+    input: String,
+    /// The query searched for, before narrowing to `filter`.
+    query: Option<Query>,
+    highlighter: Highlighter,
+    highlight: Option<Highlight>,
+    results: Option<SearchResults>,
+    /// Facet values of the query before narrowing, so every subject stays
+    /// listed while one is selected.
+    facets: Vec<(Field, String, usize)>,
+    filter: Option<(Field, String)>,
+    selected: ListState,
+    facet_state: ListState,
+    document: Option<DocumentView>,
+    status: String,
+    quit: bool,
+}
+
+impl<'a> App<'a> {
+    pub fn new(library: &'a Library, index: &'a Index, options: &Options) -> App<'a> {
+        App {
+            library,
+            index,
+            page: options.page(),
+            snippets: options.snippet_options(),
+            focus: Focus::Query,
+            input: String::new(),
+            query: None,
+            highlighter: Highlighter::default(),
+            highlight: options.highlight(),
+            results: None,
+            facets: Vec::new(),
+            filter: None,
+            selected: ListState::default(),
+            facet_state: ListState::default(),
+            document: None,
+            status: KEYS.to_string(),
+            quit: false,
+        }
+    }
+
+    /// Takes over the terminal until the user quits, restoring it afterwards.
+    pub fn run(mut self) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = self.event_loop(&mut terminal);
+        ratatui::restore();
+        result
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('c') if ctrl => self.quit = true,
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => self.focus = self.focus.step(true),
+            KeyCode::BackTab => self.focus = self.focus.step(false),
+            _ => match self.focus {
+                Focus::Query => self.query_key(key.code),
+                Focus::Results => self.results_key(key.code),
+                Focus::Document => self.document_key(key.code),
+                Focus::Facets => self.facets_key(key.code),
+            },
+        }
+    }
+
+    fn query_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => self.search(),
+            _ => {}
+        }
+    }
+
+    fn results_key(&mut self, code: KeyCode) {
+        let count = self.results.as_ref().map_or(0, |r| r.hits.len());
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.select(self.selected.selected().map(|i| i.saturating_sub(1)))
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.select(
+                self.selected
+                    .selected()
+                    .map(|i| (i + 1).min(count.saturating_sub(1))),
+            ),
+            KeyCode::Enter if self.document.is_some() => self.focus = Focus::Document,
+            KeyCode::PageDown => self.turn_page(true),
+            KeyCode::PageUp => self.turn_page(false),
+            KeyCode::Char('/') => self.focus = Focus::Query,
+            _ => {}
+        }
+    }
+
+    fn document_key(&mut self, code: KeyCode) {
+        let Some(document) = &mut self.document else {
+            return;
+        };
+        if !document.handle_key(code) && code == KeyCode::Char('/') {
+            self.focus = Focus::Query;
+        }
+    }
+
+    fn facets_key(&mut self, code: KeyCode) {
+        let count = self.facets.len();
+        let selected = self.facet_state.selected();
+        match code {
+            KeyCode::Up | KeyCode::Char('k') => self
+                .facet_state
+                .select(Some(selected.unwrap_or(0).saturating_sub(1))),
+            KeyCode::Down | KeyCode::Char('j') if count > 0 => self
+                .facet_state
+                .select(Some(selected.map_or(0, |i| (i + 1).min(count - 1)))),
+            KeyCode::Enter => {
+                if let Some((field, value, _)) = selected.and_then(|i| self.facets.get(i)) {
+                    // Selecting the current filter again clears it
+                    let filter = (*field, value.clone());
+                    self.filter = if self.filter.as_ref() == Some(&filter) {
+                        None
+                    } else {
+                        Some(filter)
+                    };
+                    self.page.offset = 0;
+                    self.rerun();
+                }
+            }
+            KeyCode::Char('/') => self.focus = Focus::Query,
+            _ => {}
+        }
+    }
+
+    fn search(&mut self) {
+        let input = self.input.trim();
+        if input.is_empty() {
+            return;
+        }
+        self.page.offset = 0;
+        let query = Query::new(input)
+            .page(self.page)
+            .snippets(self.snippets)
+            .facets(true);
+        let results = self.index.search(self.library, &query);
+        self.facets = results
+            .facets
+            .iter()
+            .flat_map(|facet| {
+                facet
+                    .counts
+                    .iter()
+                    .map(move |c| (facet.field, c.value.clone(), c.count))
+            })
+            .collect();
+        self.filter = None;
+        self.facet_state.select(None);
+        self.highlighter = Highlighter::new(&self.index.analyze(input));
+        self.show(results);
+        self.query = Some(query);
+        self.focus = Focus::Results;
+    }
+
+    /// Searches again after the page or the filter changed.
+    fn rerun(&mut self) {
+        let Some(mut query) = self.query.clone() else {
+            return;
+        };
+        query = query.page(self.page);
+        if let Some((field, value)) = &self.filter {
+            query = query.filter(*field, value);
+        }
+        let results = self.index.search(self.library, &query);
+        self.show(results);
+    }
+
+    fn turn_page(&mut self, forward: bool) {
+        let Some(results) = &self.results else { return };
+        if forward && self.page.end() < results.total_hits {
+            self.page.offset = self.page.end();
+        } else if !forward && self.page.offset > 0 {
+            self.page.offset = self.page.offset.saturating_sub(self.page.limit);
+        } else {
+            return;
+        }
+        self.rerun();
+    }
+
+    fn show(&mut self, results: SearchResults) {
+        self.status = match results.hits.len() {
+            0 => format!("No results ({} matching documents).", results.total_hits),
+            n => format!(
+                "Results {}-{} of {}{}.  {}",
+                results.offset + 1,
+                results.offset + n,
+                if results.total_hits_exact {
+                    ""
+                } else {
+                    "at least "
+                },
+                results.total_hits,
+                KEYS
+            ),
+        };
+        let first = if results.hits.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        self.results = Some(results);
+        self.select(first);
+    }
+
+    /// Selects a result and opens its document at the first hit.
+    fn select(&mut self, index: Option<usize>) {
+        self.selected.select(index);
+        let hit = index.and_then(|i| self.results.as_ref()?.hits.get(i));
+        self.document = hit.map(|hit| {
+            DocumentView::new(
+                self.library,
+                hit.id,
+                &self.highlighter,
+                self.highlight.clone(),
+            )
+        });
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [query_area, main_area, status_area] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [results_area, document_area, facets_area] = Layout::horizontal([
+            Constraint::Percentage(40),
+            Constraint::Percentage(40),
+            Constraint::Percentage(20),
+        ])
+        .areas(main_area);
+
+        frame.render_widget(
+            Paragraph::new(self.input.as_str()).block(self.block("Query", Focus::Query)),
+            query_area,
+        );
+        if self.focus == Focus::Query {
+            let x = query_area.x + 1 + self.input.chars().count() as u16;
+            frame.set_cursor_position((
+                x.min(query_area.right().saturating_sub(2)),
+                query_area.y + 1,
+            ));
+        }
+        self.draw_results(frame, results_area);
+        self.draw_document(frame, document_area);
+        self.draw_facets(frame, facets_area);
+        frame.render_widget(Paragraph::new(self.status.as_str()), status_area);
+    }
+
+    fn draw_results(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .results
+            .iter()
+            .flat_map(|results| &results.hits)
+            .map(|hit| {
+                let mut lines = vec![Line::from(format!(
+                    "{}. {} ({}, score {:.3}, {} {})",
+                    hit.rank,
+                    sanitize_line(&hit.name),
+                    sanitize_line(&hit.subject),
+                    hit.score,
+                    hit.hit_count,
+                    if hit.hit_count == 1 { "hit" } else { "hits" }
+                ))
+                .style(Style::new().add_modifier(Modifier::BOLD))];
+                for snippet in &hit.snippets {
+                    let mut spans = vec![Span::raw("  ")];
+                    if snippet.field != Field::Content {
+                        spans.push(Span::raw(format!("[{}] ", snippet.field.name())));
+                    }
+                    for (text, kind) in snippet.segments() {
+                        let text = sanitize_line(text).into_owned();
+                        spans.push(match kind {
+                            Some(kind) => {
+                                Span::styled(text, match_style(self.highlight.as_ref(), kind))
+                            }
+                            None => Span::raw(text),
+                        });
+                    }
+                    lines.push(Line::from(spans));
+                }
+                ListItem::new(Text::from(lines))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(self.block("Results", Focus::Results))
+            .highlight_style(Style::new().bg(Color::DarkGray));
+        frame.render_stateful_widget(list, area, &mut self.selected);
+    }
+
+    fn draw_document(&mut self, frame: &mut Frame, area: Rect) {
+        let block = self.block("Document", Focus::Document);
+        match &mut self.document {
+            Some(document) => document.draw(frame, area, block),
+            None => frame.render_widget(block, area),
+        }
+    }
+
+    fn draw_facets(&mut self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .facets
+            .iter()
+            .map(|(field, value, count)| {
+                let active = self
+                    .filter
+                    .as_ref()
+                    .is_some_and(|(f, v)| f == field && v == value);
+                ListItem::new(format!(
+                    "{}{} ({})",
+                    if active { "* " } else { "  " },
+                    sanitize_line(value),
+                    count
+                ))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(self.block("Subjects", Focus::Facets))
+            .highlight_style(Style::new().bg(Color::DarkGray));
+        frame.render_stateful_widget(list, area, &mut self.facet_state);
+    }
+
+    fn block(&self, title: &'static str, focus: Focus) -> Block<'static> {
+        let block = Block::bordered().title(title);
+        if self.focus == focus {
+            block.border_style(Style::new().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::cli::parse_args;
+    use crate::testing::library;
+
+    /// Runs `test` with an app showing two results per page.
+    fn with_app(test: impl FnOnce(&mut App)) {
+        let library = library();
+        let index = Index::new(&library);
+        let options = parse_args(["--limit".to_string(), "2".to_string()]).unwrap();
+        test(&mut App::new(&library, &index, &options));
+    }
+
+    fn press(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::from(code));
+    }
+
+    fn type_query(app: &mut App, query: &str) {
+        for c in query.chars() {
+            press(app, KeyCode::Char(c));
+        }
+        press(app, KeyCode::Enter);
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 20)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let width = buffer.area.width as usize;
+        let symbols: Vec<&str> = buffer.content.iter().map(|cell| cell.symbol()).collect();
+        symbols
+            .chunks(width)
+            .map(|row| row.concat())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn tab_cycles_through_the_panes() {
+        assert_eq!(Focus::Query.step(true), Focus::Results);
+        assert_eq!(Focus::Facets.step(true), Focus::Query);
+        assert_eq!(Focus::Query.step(false), Focus::Facets);
+        with_app(|app| {
+            press(app, KeyCode::Tab);
+            press(app, KeyCode::Tab);
+            assert_eq!(app.focus, Focus::Document);
+            press(app, KeyCode::BackTab);
+            assert_eq!(app.focus, Focus::Results);
+            press(app, KeyCode::Char('/'));
+            assert_eq!(app.focus, Focus::Query);
+        });
+    }
+
+    #[test]
+    fn searching_selects_the_first_result_and_opens_it() {
+        with_app(|app| {
+            type_query(app, "ring");
+            assert_eq!(app.focus, Focus::Results);
+            let results = app.results.as_ref().unwrap();
+            assert_eq!(results.total_hits, 3);
+            assert_eq!(results.hits.len(), 2);
+            assert_eq!(app.selected.selected(), Some(0));
+            let document = app.document.as_ref().unwrap();
+            assert!(document.position().ends_with("hit 1 of 2"));
+
+            press(app, KeyCode::Down);
+            press(app, KeyCode::Down);
+            assert_eq!(app.selected.selected(), Some(1), "stops at the last result");
+
+            let screen = screen(app);
+            assert!(screen.contains("Results 1-2 of 3."), "{}", screen);
+            assert!(screen.contains("Document─Numbers 6 (Bible)"), "{}", screen);
+            assert!(screen.contains("Numbers 6"), "{}", screen);
+        });
+    }
+
+    #[test]
+    fn pages_turn_within_the_results() {
+        with_app(|app| {
+            type_query(app, "ring");
+            press(app, KeyCode::PageUp);
+            assert_eq!(app.page.offset, 0);
+            press(app, KeyCode::PageDown);
+            assert_eq!(app.page.offset, 2);
+            assert_eq!(app.results.as_ref().unwrap().hits.len(), 1);
+            press(app, KeyCode::PageDown);
+            assert_eq!(app.page.offset, 2, "no page after the last");
+            press(app, KeyCode::PageUp);
+            assert_eq!(app.page.offset, 0);
+        });
+    }
+
+    #[test]
+    fn hits_cycle_in_the_document() {
+        with_app(|app| {
+            type_query(app, "ring");
+            press(app, KeyCode::Enter);
+            assert_eq!(app.focus, Focus::Document);
+            press(app, KeyCode::Char('n'));
+            assert!(app
+                .document
+                .as_ref()
+                .unwrap()
+                .position()
+                .ends_with("hit 2 of 2"));
+            press(app, KeyCode::Char('n'));
+            assert!(
+                app.document
+                    .as_ref()
+                    .unwrap()
+                    .position()
+                    .ends_with("hit 1 of 2"),
+                "wraps around"
+            );
+            press(app, KeyCode::Char('p'));
+            assert!(app
+                .document
+                .as_ref()
+                .unwrap()
+                .position()
+                .ends_with("hit 2 of 2"));
+        });
+    }
+
+    #[test]
+    fn selecting_a_subject_filters_and_again_clears() {
+        with_app(|app| {
+            type_query(app, "ring");
+            app.focus = Focus::Facets;
+            press(app, KeyCode::Down);
+            assert_eq!(app.facets[0], (Field::Subject, "Bible".to_string(), 2));
+            press(app, KeyCode::Enter);
+            assert_eq!(app.filter, Some((Field::Subject, "Bible".to_string())));
+            let results = app.results.as_ref().unwrap();
+            assert_eq!(results.total_hits, 2);
+            assert_eq!(app.facets.len(), 2, "every subject stays listed");
+
+            press(app, KeyCode::Enter);
+            assert_eq!(app.filter, None);
+            assert_eq!(app.results.as_ref().unwrap().total_hits, 3);
+        });
+    }
+
+    #[test]
+    fn escape_quits() {
+        with_app(|app| {
+            press(app, KeyCode::Esc);
+            assert!(app.quit);
+        });
+    }
+}